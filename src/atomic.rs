@@ -0,0 +1,138 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Renaming without ever replacing what is already there.
+//!
+//! Checking that the target doesn't exist and then calling [`fs::rename`]
+//! leaves a window in which a file that appears at the target is silently
+//! overwritten. These helpers close it.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::normalize::Normalization;
+
+/// Moves `from` to `to`, failing with [`ErrorKind::AlreadyExists`] instead of
+/// replacing anything at `to`.
+///
+/// Uses `renameat2` with `RENAME_NOREPLACE` (or `renamex_np` with
+/// `RENAME_EXCL`) where the platform and filesystem support it, and falls back
+/// to creating a hard link at `to` and removing `from` otherwise. Directories
+/// can't be hard linked, so for them the fallback is a check right before the
+/// rename.
+///
+/// # Errors
+///
+/// Returns an error if `to` exists, or the rename fails for any other reason.
+pub fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios"
+    ))]
+    {
+        use rustix::{
+            fs::{renameat_with, RenameFlags, CWD},
+            io::Errno,
+        };
+
+        match renameat_with(CWD, from, CWD, to, RenameFlags::NOREPLACE) {
+            Ok(()) => return Ok(()),
+            // Not supported by the kernel or the filesystem.
+            Err(Errno::INVAL | Errno::NOSYS | Errno::NOTSUP) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    link_and_unlink(from, to)
+}
+
+/// Whether `to` is only another name for `from`, because the two only differ
+/// in case or Unicode normalisation and the filesystem ignores it.
+///
+/// Such a target exists, but only as the item itself, so renaming to it has to
+/// go through a temporary name. Hard links whose names differ in case are
+/// both listed in their directory, and are not the same name.
+pub fn is_case_change(from: &Path, to: &Path) -> bool {
+    let (Some(a), Some(b)) = (from.file_name(), to.file_name()) else {
+        return false;
+    };
+
+    if a == b
+        || from.parent() != to.parent()
+        || fold(&a.to_string_lossy()) != fold(&b.to_string_lossy())
+        || !is_same_file(from, to)
+    {
+        return false;
+    }
+
+    let dir = from
+        .parent()
+        .filter(|x| !x.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    fs::read_dir(dir).is_ok_and(|mut entries| !entries.any(|x| x.is_ok_and(|x| x.file_name() == b)))
+}
+
+/// Folds case and normalisation, as filesystems that ignore them do.
+fn fold(name: &str) -> String {
+    Normalization::Nfc.apply(&name.to_lowercase())
+}
+
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn link_and_unlink(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(from)?.is_dir() {
+        if fs::symlink_metadata(to).is_ok() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("`{}` already exists", to.to_string_lossy()),
+            ));
+        }
+
+        return fs::rename(from, to);
+    }
+
+    // Creating the link fails if anything is at `to`, atomically.
+    fs::hard_link(from, to)?;
+    fs::remove_file(from)
+}
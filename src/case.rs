@@ -0,0 +1,209 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Converting names between styles of case.
+
+/// Words that stay lower case in titles, unless they start or end them.
+const SMALL_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "en", "for", "from", "if", "in", "nor", "of", "on",
+    "or", "per", "so", "the", "to", "up", "via", "vs", "with", "yet",
+];
+
+/// A style of case that names can be converted to with `--case`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Case {
+    /// `every word in lower case`
+    #[default]
+    Lower,
+    /// `EVERY WORD IN UPPER CASE`
+    Upper,
+    /// `Every Word Capitalised, but Not Small Words`
+    Title,
+    /// `Only the first word capitalised`
+    Sentence,
+    /// `words_joined_with_underscores`
+    Snake,
+    /// `words-joined-with-dashes`
+    Kebab,
+    /// `wordsJoinedTogether`
+    Camel,
+    /// `WordsJoinedTogether`
+    Pascal,
+}
+
+impl From<&String> for Case {
+    fn from(a: &String) -> Self {
+        let a = a.to_lowercase();
+
+        match a.as_ref() {
+            "lower" => Self::Lower,
+            "upper" => Self::Upper,
+            "title" => Self::Title,
+            "sentence" => Self::Sentence,
+            "snake" => Self::Snake,
+            "kebab" => Self::Kebab,
+            "camel" => Self::Camel,
+            "pascal" => Self::Pascal,
+            _ => unreachable!(
+                "If this is reached, something in validation has gone *horribly* wrong."
+            ),
+        }
+    }
+}
+
+impl Case {
+    /// Converts `text` to the case.
+    ///
+    /// Lower, upper, title and sentence case only change letters. The other
+    /// styles split `text` into words first, at anything that isn't a letter
+    /// or a digit and wherever a lower-case letter is followed by an
+    /// upper-case one, as in `fileName`, and join them again in their own
+    /// way.
+    #[must_use]
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Self::Lower => text.to_lowercase(),
+            Self::Upper => text.to_uppercase(),
+            Self::Title => title(text),
+            Self::Sentence => {
+                let text = text.to_lowercase();
+
+                match text.find(char::is_alphanumeric) {
+                    Some(i) => {
+                        let (start, rest) = text.split_at(i);
+                        let mut chars = rest.chars();
+                        let first = chars.next().map(char::to_uppercase);
+
+                        format!(
+                            "{start}{}{}",
+                            first.into_iter().flatten().collect::<String>(),
+                            chars.as_str()
+                        )
+                    }
+                    None => text,
+                }
+            }
+            Self::Snake => join(text, "_", str::to_lowercase, str::to_lowercase),
+            Self::Kebab => join(text, "-", str::to_lowercase, str::to_lowercase),
+            Self::Camel => join(text, "", str::to_lowercase, capitalise),
+            Self::Pascal => join(text, "", capitalise, capitalise),
+        }
+    }
+}
+
+/// Capitalises every word of `text` but small ones in the middle, keeping
+/// everything between words as it is.
+fn title(text: &str) -> String {
+    let mut words = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices() {
+        match (start, c.is_alphanumeric() || c == '\'') {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                words.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(s) = start {
+        words.push(s..text.len());
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (n, range) in words.iter().enumerate() {
+        let word = &text[range.clone()];
+        let lower = word.to_lowercase();
+        let small = n > 0 && n + 1 < words.len() && SMALL_WORDS.contains(&lower.as_str());
+
+        result.push_str(&text[last..range.start]);
+        result.push_str(&if small { lower } else { capitalise(word) });
+        last = range.end;
+    }
+
+    result.push_str(&text[last..]);
+    result
+}
+
+/// Upper-cases the first letter of `word`, and lower-cases the rest.
+fn capitalise(word: &str) -> String {
+    let mut chars = word.chars();
+
+    chars.next().map_or_else(String::new, |first| {
+        first
+            .to_uppercase()
+            .chain(chars.as_str().to_lowercase().chars())
+            .collect()
+    })
+}
+
+/// Splits `text` into words, and joins them with `separator`, converting the
+/// first word with `first` and the others with `rest`.
+fn join(
+    text: &str,
+    separator: &str,
+    first: fn(&str) -> String,
+    rest: fn(&str) -> String,
+) -> String {
+    words(text)
+        .iter()
+        .enumerate()
+        .map(|(i, x)| if i == 0 { first(x) } else { rest(x) })
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// The words in `text`, as in `HTTPServer v2_final` being `HTTP`, `Server`,
+/// `v2` and `final`.
+fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+
+    for part in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+    {
+        let chars = part.char_indices().collect::<Vec<_>>();
+        let mut start = 0;
+
+        for (n, &(i, c)) in chars.iter().enumerate().skip(1) {
+            let previous = chars[n - 1].1;
+            let next = chars.get(n + 1).map(|x| x.1);
+            // `fileName`, and `HTTPServer` before the `S`.
+            let boundary = c.is_uppercase()
+                && (previous.is_lowercase()
+                    || (previous.is_uppercase() && next.is_some_and(char::is_lowercase)));
+
+            if boundary {
+                words.push(&part[start..i]);
+                start = i;
+            }
+        }
+
+        words.push(&part[start..]);
+    }
+
+    words
+}
@@ -0,0 +1,309 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Resolving items whose new name is already taken.
+
+use crate::{atomic::is_case_change, RenameItem};
+use color_eyre::{eyre::eyre, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// What to do when an item's new name is already taken, either by a file on
+/// disk or by another item in the same plan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Refuse to rename anything
+    #[default]
+    FailAll,
+    /// Leave the item where it is
+    Skip,
+    /// Replace whatever has the name
+    Overwrite,
+    /// Give the item a numbered name instead, as in `name (1).ext`
+    AutoSuffix,
+    /// Keep whichever of the two was modified last
+    KeepNewer,
+    /// Keep whichever of the two is larger
+    KeepLarger,
+    /// Ask what to do for every conflict
+    Prompt,
+}
+
+impl From<&String> for ConflictPolicy {
+    fn from(a: &String) -> Self {
+        let a = a.to_lowercase();
+
+        match a.as_ref() {
+            "fail" | "fail-all" => Self::FailAll,
+            "skip" => Self::Skip,
+            "overwrite" => Self::Overwrite,
+            "suffix" | "auto-suffix" => Self::AutoSuffix,
+            "keep-newer" => Self::KeepNewer,
+            "keep-larger" => Self::KeepLarger,
+            "prompt" | "interactive" => Self::Prompt,
+            _ => unreachable!(
+                "If this is reached, something in validation has gone *horribly* wrong."
+            ),
+        }
+    }
+}
+
+/// How a conflict was resolved while planning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// The item is left where it is
+    Skipped(RenameItem),
+    /// The item replaces the file that already had its new name
+    Overwritten(RenameItem),
+    /// The item was given a different new name
+    Suffixed {
+        /// The item as it was planned before the conflict
+        item: RenameItem,
+        /// The name it was given instead
+        new_path: PathBuf,
+    },
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Skipped(item) => write!(
+                f,
+                "[SKIP] `{}`: `{}` is already taken.",
+                item.original_path.to_string_lossy(),
+                item.new_path.to_string_lossy()
+            ),
+            Self::Overwritten(item) => write!(
+                f,
+                "[OVERWRITE] `{}` will replace `{}`, which can't be brought back by a rollback or `undo`.",
+                item.original_path.to_string_lossy(),
+                item.new_path.to_string_lossy()
+            ),
+            Self::Suffixed { item, new_path } => write!(
+                f,
+                "[SUFFIX] `{}` will be renamed to `{}`, as `{}` is already taken.",
+                item.original_path.to_string_lossy(),
+                new_path.to_string_lossy(),
+                item.new_path.to_string_lossy()
+            ),
+        }
+    }
+}
+
+/// What to do about a single conflict.
+enum Action {
+    Skip,
+    Replace,
+    Suffix,
+}
+
+/// Applies `policy` to every item whose new name is taken, either by a file on
+/// disk that the plan leaves alone, or by an earlier item.
+///
+/// With [`ConflictPolicy::FailAll`], items are returned as they are, so that
+/// validation reports the conflicts. Items whose new name belongs to an item
+/// that ends up left where it is are left where they are as well.
+///
+/// # Errors
+///
+/// Returns an error if the user aborts when prompted, or the metadata needed
+/// to compare two items can't be read.
+pub fn resolve(
+    items: Vec<RenameItem>,
+    policy: ConflictPolicy,
+) -> Result<(Vec<RenameItem>, Vec<Resolution>)> {
+    if policy == ConflictPolicy::FailAll {
+        return Ok((items, Vec::new()));
+    }
+
+    let sources = items
+        .iter()
+        .map(|x| x.original_path.clone())
+        .collect::<HashSet<_>>();
+    let mut resolved: Vec<Option<RenameItem>> = Vec::with_capacity(items.len());
+    let mut taken: HashMap<PathBuf, usize> = HashMap::new();
+    let mut resolutions = Vec::new();
+
+    for mut item in items {
+        let on_disk = item.new_path.exists()
+            && !sources.contains(&item.new_path)
+            && !is_case_change(&item.original_path, &item.new_path);
+        let holder = taken.get(&item.new_path).copied();
+
+        if !on_disk && holder.is_none() {
+            taken.insert(item.new_path.clone(), resolved.len());
+            resolved.push(Some(item));
+            continue;
+        }
+
+        // The other side of the conflict: an earlier item, or the file on disk.
+        let other = holder
+            .and_then(|i| resolved[i].as_ref())
+            .map_or_else(|| item.new_path.clone(), |x| x.original_path.clone());
+
+        let action = match policy {
+            ConflictPolicy::FailAll => unreachable!("handled above"),
+            ConflictPolicy::Skip => Action::Skip,
+            ConflictPolicy::Overwrite => Action::Replace,
+            ConflictPolicy::AutoSuffix => Action::Suffix,
+            ConflictPolicy::KeepNewer => {
+                if modified(&item.original_path)? > modified(&other)? {
+                    Action::Replace
+                } else {
+                    Action::Skip
+                }
+            }
+            ConflictPolicy::KeepLarger => {
+                if fs::metadata(&item.original_path)?.len() > fs::metadata(&other)?.len() {
+                    Action::Replace
+                } else {
+                    Action::Skip
+                }
+            }
+            ConflictPolicy::Prompt => prompt(&item, &other)?,
+        };
+
+        match action {
+            Action::Skip => resolutions.push(Resolution::Skipped(item)),
+            Action::Replace => {
+                // Items of the plan are never overwritten, the earlier one
+                // stays where it is instead.
+                if let Some(loser) = holder.and_then(|i| resolved[i].take()) {
+                    resolutions.retain(|x| x != &Resolution::Overwritten(loser.clone()));
+                    resolutions.push(Resolution::Skipped(loser));
+                }
+
+                item.overwrite = on_disk;
+
+                if on_disk {
+                    resolutions.push(Resolution::Overwritten(item.clone()));
+                }
+
+                taken.insert(item.new_path.clone(), resolved.len());
+                resolved.push(Some(item));
+            }
+            Action::Suffix => {
+                let new_path = suffixed(&item.new_path, |x| {
+                    !x.exists() && !sources.contains(x) && !taken.contains_key(x)
+                });
+
+                resolutions.push(Resolution::Suffixed {
+                    item: item.clone(),
+                    new_path: new_path.clone(),
+                });
+                item.new_path = new_path;
+
+                taken.insert(item.new_path.clone(), resolved.len());
+                resolved.push(Some(item));
+            }
+        }
+    }
+
+    let mut resolved = resolved.into_iter().flatten().collect::<Vec<_>>();
+
+    // An item that stays keeps its name taken, so an item that was to be
+    // renamed to it has to stay too, and so on down the chain.
+    loop {
+        let moving = resolved
+            .iter()
+            .map(|x| &x.original_path)
+            .collect::<HashSet<_>>();
+        let staying = sources
+            .iter()
+            .filter(|x| !moving.contains(x))
+            .cloned()
+            .collect::<HashSet<_>>();
+        let (blocked, rest) = resolved
+            .into_iter()
+            .partition::<Vec<_>, _>(|x| staying.contains(&x.new_path));
+
+        resolved = rest;
+
+        if blocked.is_empty() {
+            break;
+        }
+
+        resolutions.extend(blocked.into_iter().map(Resolution::Skipped));
+    }
+
+    Ok((resolved, resolutions))
+}
+
+fn modified(path: &Path) -> Result<std::time::SystemTime> {
+    Ok(fs::metadata(path)?.modified()?)
+}
+
+/// Finds the first of `name (1).ext`, `name (2).ext`, ... that is `free`.
+fn suffixed(path: &Path, free: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default();
+    let ext = path.extension();
+
+    let mut n = 1;
+
+    loop {
+        let mut name = stem.to_os_string();
+
+        name.push(format!(" ({n})"));
+
+        if let Some(ext) = ext {
+            name.push(".");
+            name.push(ext);
+        }
+
+        let candidate = path.with_file_name(name);
+
+        if free(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+fn prompt(item: &RenameItem, other: &Path) -> Result<Action> {
+    loop {
+        eprint!(
+            "`{}` -> `{}`: already taken by `{}`. [s]kip, [o]verwrite, [r]ename with a suffix or [a]bort? ",
+            item.original_path.to_string_lossy(),
+            item.new_path.to_string_lossy(),
+            other.to_string_lossy()
+        );
+        io::stderr().flush()?;
+
+        let mut answer = String::new();
+
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Err(eyre!("Aborted: no answer given."));
+        }
+
+        match answer.trim().to_lowercase().as_ref() {
+            "s" | "skip" => return Ok(Action::Skip),
+            "o" | "overwrite" => return Ok(Action::Replace),
+            "r" | "rename" => return Ok(Action::Suffix),
+            "a" | "abort" => return Err(eyre!("Aborted.")),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,454 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Carrying out a [`RenamePlan`].
+
+use crate::{
+    atomic::{is_case_change, rename_no_replace},
+    journal::JournalWriter,
+    RenameItem, RenamePlan,
+};
+use color_eyre::{eyre::eyre, Result};
+use paris::{info, warn};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A single move on disk, as performed by the executor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub overwrite: bool,
+}
+
+/// Performs every rename in the given plan.
+///
+/// The plan is validated again before anything is renamed, so items that were
+/// changed after [`plan()`](crate::plan()) are checked as well.
+///
+/// Renames are ordered so that targets vacated by other items in the plan are
+/// reused, and cycles (such as swapping two names) are broken by moving one
+/// item to a temporary name first. Otherwise the plan's order is kept. Items
+/// whose name only changes in case, on a filesystem that ignores case, are
+/// moved to a temporary name first as well.
+///
+/// If the plan has a [journal](RenamePlan::journal) directory, the moves are
+/// written there before anything happens, and every completed move is
+/// recorded as it happens, so the run can be resumed or undone later.
+///
+/// # Errors
+///
+/// Returns an error if [`RenamePlan::validate()`] reports any problems, in
+/// which case nothing is renamed. Individual renames that fail are logged and
+/// skipped, unless the plan is [transactional](RenamePlan::transactional): then
+/// the first failure rolls back every completed rename, in reverse order, and
+/// an error is returned once that is done. Otherwise, an error listing them is
+/// returned if failures left items under temporary names.
+#[allow(clippy::needless_pass_by_value)] // The plan is used up by executing it
+pub fn execute(plan: RenamePlan) -> Result<()> {
+    let problems = plan.validate();
+
+    if !problems.is_empty() {
+        return Err(eyre!(
+            "Refusing to rename: the plan has {} problem(s).",
+            problems.len()
+        ));
+    }
+
+    let steps = order(&plan.items, is_case_change);
+    let journal = match &plan.journal {
+        Some(dir) if !plan.dry_run && !steps.is_empty() => {
+            Some(JournalWriter::create(dir, &plan.folder, &steps)?)
+        }
+        _ => None,
+    };
+
+    perform(steps, &plan, journal)
+}
+
+/// Performs the given moves exactly in order, with the settings of `plan`.
+///
+/// If there is a journal, every move is recorded in it, and it is marked as
+/// finished once there is nothing left to do, even if some moves failed.
+/// Failures are handled as described for [`execute()`].
+pub fn perform(
+    steps: Vec<Step>,
+    plan: &RenamePlan,
+    mut journal: Option<JournalWriter>,
+) -> Result<()> {
+    let result = perform_steps(steps, plan, journal.as_mut());
+
+    if let Some(journal) = &mut journal {
+        if let Err(e) = journal.finish() {
+            warn!("Unable to write to the journal: {}", e);
+        }
+        if result.is_ok() {
+            info!(
+                "This run can be undone with `rena undo {}`.",
+                journal.run_id()
+            );
+        }
+    }
+
+    result
+}
+
+fn perform_steps(
+    steps: Vec<Step>,
+    plan: &RenamePlan,
+    mut journal: Option<&mut JournalWriter>,
+) -> Result<()> {
+    let mut completed = Vec::new();
+    // Temporary names are moved on from by a later step.
+    let last_from = steps
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (x.from.clone(), i))
+        .collect::<HashMap<_, _>>();
+    let temporary = steps
+        .iter()
+        .enumerate()
+        .filter(|&(i, x)| last_from.get(&x.to).is_some_and(|&j| j > i))
+        .map(|(_, x)| x.to.clone())
+        .collect::<HashSet<_>>();
+    // Items that are under a temporary name, and where they were before.
+    let mut parked: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    for x in steps {
+        if plan.dry_run {
+            info!(
+                "[DRY RUN]: `{}` -> `{}`",
+                x.from.to_string_lossy(),
+                x.to.to_string_lossy()
+            );
+            continue;
+        }
+
+        let result = create_parent(&x.to, plan.create_dirs).and_then(|()| {
+            if x.overwrite {
+                fs::rename(&x.from, &x.to)
+            } else {
+                rename_no_replace(&x.from, &x.to)
+            }
+        });
+
+        match result {
+            Ok(()) => {
+                if plan.verbose {
+                    info!(
+                        "[DONE] `{}` -> `{}`",
+                        x.from.to_string_lossy(),
+                        x.to.to_string_lossy()
+                    );
+                }
+                record(journal.as_deref_mut(), &x.from, &x.to);
+                parked.retain(|(temp, _)| *temp != x.from);
+
+                if temporary.contains(&x.to) {
+                    parked.push((x.to.clone(), x.from.clone()));
+                }
+                completed.push(x);
+            }
+            Err(e) => {
+                if e.kind() == ErrorKind::AlreadyExists {
+                    warn!(
+                        "Item `{}` already exists, unable to rename.",
+                        x.to.to_string_lossy()
+                    );
+                } else {
+                    warn!(
+                        "[FAIL] `{}` -> `{}`: {}",
+                        x.from.to_string_lossy(),
+                        x.to.to_string_lossy(),
+                        e
+                    );
+                }
+                if plan.transactional {
+                    return rollback(completed, journal);
+                }
+            }
+        }
+    }
+
+    if parked.is_empty() {
+        return Ok(());
+    }
+
+    let parked = parked
+        .iter()
+        .map(|(temp, from)| {
+            format!(
+                "`{}` (was `{}`)",
+                temp.to_string_lossy(),
+                from.to_string_lossy()
+            )
+        })
+        .collect::<Vec<_>>();
+
+    Err(eyre!(
+        "Some renames failed, and {} item(s) were left under a temporary name: {}",
+        parked.len(),
+        parked.join(", ")
+    ))
+}
+
+/// Creates the directory `path` is in if it doesn't exist yet, and if that is
+/// allowed at all.
+fn create_parent(path: &Path, create: bool) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if create && !parent.exists() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+/// Reverses the given moves, most recent first, and reports how that went.
+fn rollback(completed: Vec<Step>, mut journal: Option<&mut JournalWriter>) -> Result<()> {
+    let mut rolled_back = 0;
+    let mut stuck = 0;
+
+    for x in completed.into_iter().rev() {
+        let result = rename_no_replace(&x.to, &x.from);
+
+        match result {
+            Ok(()) => {
+                info!(
+                    "[ROLLED BACK] `{}` -> `{}`",
+                    x.to.to_string_lossy(),
+                    x.from.to_string_lossy()
+                );
+                record(journal.as_deref_mut(), &x.to, &x.from);
+                rolled_back += 1;
+            }
+            Err(e) => {
+                warn!(
+                    "[STUCK] `{}` could not be moved back to `{}`: {}",
+                    x.to.to_string_lossy(),
+                    x.from.to_string_lossy(),
+                    e
+                );
+                stuck += 1;
+            }
+        }
+    }
+
+    if stuck == 0 {
+        Err(eyre!(
+            "A rename failed, all {rolled_back} completed rename(s) were rolled back."
+        ))
+    } else {
+        Err(eyre!(
+            "A rename failed, {rolled_back} rename(s) were rolled back and {stuck} could not be."
+        ))
+    }
+}
+
+/// Appends a move to the journal, if there is one.
+///
+/// The move has already happened by then, so failing to record it is only
+/// worth a warning.
+fn record(journal: Option<&mut JournalWriter>, from: &Path, to: &Path) {
+    if let Some(journal) = journal {
+        if let Err(e) = journal.record(from, to) {
+            warn!("Unable to write to the journal: {}", e);
+        }
+    }
+}
+
+/// Turns a validated plan into moves that never overwrite one another.
+///
+/// An item depends on the item whose original path is its target. Since
+/// targets are unique, these dependencies form plain chains and cycles: chains
+/// are run from their free end, and each cycle is opened up by moving one of
+/// its items out of the way to a temporary name.
+///
+/// Directories also have to be renamed after everything inside them, and
+/// before anything is moved into where they were or where they end up, so
+/// that no item's path is invalidated by a rename of one of its parents.
+///
+/// Items for which `case_change` is true, given where the item is and its
+/// target, go through a temporary name as well.
+#[allow(clippy::too_many_lines)]
+pub fn order(items: &[RenameItem], case_change: impl Fn(&Path, &Path) -> bool) -> Vec<Step> {
+    let by_source = items
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (x.original_path.as_path(), i))
+        .collect::<HashMap<_, _>>();
+    let by_target = items
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (x.new_path.as_path(), i))
+        .collect::<HashMap<_, _>>();
+
+    let mut waiting = vec![0_usize; items.len()];
+    let mut released = vec![Vec::new(); items.len()];
+
+    for (i, x) in items.iter().enumerate() {
+        if by_source.get(x.new_path.as_path()).is_some_and(|&j| j != i) {
+            waiting[i] += 1;
+        }
+
+        for parent in x.original_path.ancestors().skip(1) {
+            if let Some(&j) = by_source.get(parent).filter(|&&j| j != i) {
+                waiting[j] += 1;
+                released[i].push(j);
+            }
+        }
+
+        // A directory that is moved away is only created again afterwards.
+        for parent in x.new_path.ancestors().skip(1) {
+            if x.original_path.starts_with(parent) {
+                continue;
+            }
+            if let Some(&j) = by_source.get(parent).filter(|&&j| j != i) {
+                waiting[i] += 1;
+                released[j].push(i);
+            }
+        }
+
+        let parents = x.original_path.ancestors().skip(1);
+
+        for parent in parents.chain(x.new_path.ancestors().skip(1)) {
+            if let Some(&j) = by_target.get(parent).filter(|&&j| j != i) {
+                waiting[i] += 1;
+                released[j].push(i);
+            }
+        }
+    }
+
+    let mut current = items
+        .iter()
+        .map(|x| x.original_path.clone())
+        .collect::<Vec<_>>();
+    let mut done = vec![false; items.len()];
+    let mut ready = (0..items.len())
+        .filter(|&i| waiting[i] == 0)
+        .collect::<VecDeque<_>>();
+    let mut steps = Vec::with_capacity(items.len());
+    let mut temp_count = 0;
+    let release = |k: usize, waiting: &mut [usize], ready: &mut VecDeque<usize>| {
+        if waiting[k] == 1 {
+            ready.push_back(k);
+        }
+        waiting[k] = waiting[k].saturating_sub(1);
+    };
+
+    loop {
+        while let Some(i) = ready.pop_front() {
+            let item = &items[i];
+
+            if case_change(&current[i], &item.new_path) {
+                // The filesystem sees both names as the same, so the item has
+                // to be moved away from it first.
+                let temp = temp_path(&item.original_path, &mut temp_count, &by_source, &by_target);
+
+                steps.push(Step {
+                    from: current[i].clone(),
+                    to: temp.clone(),
+                    overwrite: false,
+                });
+                steps.push(Step {
+                    from: temp,
+                    to: item.new_path.clone(),
+                    overwrite: item.overwrite,
+                });
+            } else if current[i] != item.new_path {
+                steps.push(Step {
+                    from: current[i].clone(),
+                    to: item.new_path.clone(),
+                    overwrite: item.overwrite,
+                });
+            }
+            done[i] = true;
+
+            // An item parked under a temporary name has released its
+            // dependent already.
+            if current[i] == item.original_path {
+                if let Some(&k) = by_target.get(item.original_path.as_path()) {
+                    if k != i {
+                        release(k, &mut waiting, &mut ready);
+                    }
+                }
+            }
+            current[i].clone_from(&item.new_path);
+
+            for &k in &released[i] {
+                release(k, &mut waiting, &mut ready);
+            }
+        }
+
+        // Everything left over is waiting on a cycle.
+        let Some(c) = (0..items.len()).find(|&i| !done[i] && current[i] == items[i].original_path)
+        else {
+            // Only possible if items are nested in each other in ways that
+            // can't be satisfied, so just go in plan order.
+            match done.iter().position(|&x| !x) {
+                Some(c) => {
+                    waiting[c] = 0;
+                    ready.push_back(c);
+                    continue;
+                }
+                None => break,
+            }
+        };
+        let item = &items[c];
+        let temp = temp_path(&item.original_path, &mut temp_count, &by_source, &by_target);
+
+        steps.push(Step {
+            from: current[c].clone(),
+            to: temp.clone(),
+            overwrite: false,
+        });
+        current[c] = temp;
+
+        if let Some(&k) = by_target.get(item.original_path.as_path()) {
+            release(k, &mut waiting, &mut ready);
+        }
+    }
+
+    steps
+}
+
+/// Finds a name next to `path` that is neither on disk nor used by the plan.
+fn temp_path(
+    path: &Path,
+    count: &mut usize,
+    by_source: &HashMap<&Path, usize>,
+    by_target: &HashMap<&Path, usize>,
+) -> PathBuf {
+    loop {
+        let candidate = path.with_file_name(format!(".rena-{}-{}.tmp", process::id(), count));
+        *count += 1;
+
+        if !candidate.exists()
+            && !by_source.contains_key(candidate.as_path())
+            && !by_target.contains_key(candidate.as_path())
+        {
+            return candidate;
+        }
+    }
+}
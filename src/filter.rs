@@ -0,0 +1,187 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Filters that transform values in templates and replacements.
+
+use crate::case::Case;
+use color_eyre::{eyre::eyre, Result};
+use deunicode::deunicode_char;
+
+/// A transformation applied to a value, as in `{stem|lower}`.
+///
+/// Filters follow a value after `|`, and are applied from left to right.
+/// Arguments follow the filter's name after `:`.
+///
+/// - `lower`, `upper`: changes the value's case
+/// - `title`: converts the value to title case, as
+///   [`Case::Title`](crate::Case::Title) does
+/// - `trim`: removes whitespace at either end
+/// - `ascii[:FALLBACK]`: transliterates the value to ASCII, as in `café` to
+///   `cafe`, replacing characters that can't be with `FALLBACK` or `_`
+/// - `slug[:SEPARATOR]`: transliterates and lower-cases the value, and
+///   replaces everything that isn't a letter or a digit with single dashes,
+///   or `SEPARATOR`
+/// - `pad:WIDTH[:FILL]`: pads the value on the left to `WIDTH` characters,
+///   with `FILL` or `0`
+/// - `truncate:LENGTH`: keeps at most `LENGTH` characters
+/// - `replace:FROM:TO`: replaces every `FROM` with `TO`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Lower,
+    Upper,
+    Title,
+    Trim,
+    Ascii(String),
+    Slug(String),
+    Pad { width: usize, fill: char },
+    Truncate(usize),
+    Replace { from: String, to: String },
+}
+
+impl Filter {
+    /// Parses a chain of filters such as `lower|truncate:10`.
+    pub fn parse_chain(text: &str) -> Result<Vec<Self>> {
+        text.split('|').map(Self::parse).collect()
+    }
+
+    /// Parses a single filter, such as `pad:3`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut args = text.split(':');
+        let name = args.next().unwrap_or_default().trim();
+        let args = args.collect::<Vec<_>>();
+        let number = |x: &str| {
+            x.trim()
+                .parse::<usize>()
+                .map_err(|_| eyre!("Filter `{name}` needs a number, not `{x}`."))
+        };
+
+        let filter = match (name, args.as_slice()) {
+            ("lower", []) => Self::Lower,
+            ("upper", []) => Self::Upper,
+            ("title", []) => Self::Title,
+            ("trim", []) => Self::Trim,
+            ("ascii", []) => Self::Ascii("_".to_owned()),
+            ("ascii", [fallback]) => Self::Ascii((*fallback).to_owned()),
+            ("slug", []) => Self::Slug("-".to_owned()),
+            ("slug", [separator]) => Self::Slug((*separator).to_owned()),
+            ("pad", [width]) => Self::Pad {
+                width: number(width)?,
+                fill: '0',
+            },
+            ("pad", [width, fill]) => {
+                let mut chars = fill.chars();
+
+                match (chars.next(), chars.next()) {
+                    (Some(fill), None) => Self::Pad {
+                        width: number(width)?,
+                        fill,
+                    },
+                    _ => return Err(eyre!("Filter `pad` needs a single fill character.")),
+                }
+            }
+            ("truncate", [length]) => Self::Truncate(number(length)?),
+            ("replace", [from, to]) if !from.is_empty() => Self::Replace {
+                from: (*from).to_owned(),
+                to: (*to).to_owned(),
+            },
+            (
+                "lower" | "upper" | "title" | "trim" | "ascii" | "slug" | "pad" | "truncate"
+                | "replace",
+                _,
+            ) => return Err(eyre!("Wrong arguments for filter `{text}`.")),
+            _ => return Err(eyre!("Unknown filter `{name}`.")),
+        };
+
+        Ok(filter)
+    }
+
+    /// Applies the filter to `value`.
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Self::Lower => value.to_lowercase(),
+            Self::Upper => value.to_uppercase(),
+            Self::Title => Case::Title.apply(value),
+            Self::Trim => value.trim().to_owned(),
+            Self::Ascii(fallback) => transliterate(value, fallback),
+            Self::Slug(separator) => slug(value, separator),
+            Self::Pad { width, fill } => {
+                let missing = width.saturating_sub(value.chars().count());
+
+                std::iter::repeat_n(*fill, missing)
+                    .chain(value.chars())
+                    .collect()
+            }
+            Self::Truncate(length) => value.chars().take(*length).collect(),
+            Self::Replace { from, to } => value.replace(from, to),
+        }
+    }
+}
+
+/// Converts `value` to ASCII, replacing characters that have no ASCII
+/// equivalent with `fallback`.
+///
+/// Characters that are written as several words, such as `北京` as
+/// `Bei Jing`, are kept apart by spaces, but not from punctuation.
+pub fn transliterate(value: &str, fallback: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut space = false;
+    let mut buffer = [0; 4];
+
+    for c in value.chars() {
+        let text = if c.is_ascii() {
+            &*c.encode_utf8(&mut buffer)
+        } else {
+            deunicode_char(c).unwrap_or(fallback)
+        };
+        let word = text.trim_end();
+
+        if word.is_empty() {
+            result.push_str(text);
+            space = false;
+            continue;
+        }
+        if space && word.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+            result.push(' ');
+        }
+
+        result.push_str(word);
+        space = word.len() < text.len();
+    }
+
+    result
+}
+
+/// Transliterates and lower-cases `value`, joining every run of letters and
+/// digits with `separator`.
+pub fn slug(value: &str, separator: &str) -> String {
+    transliterate(value, "")
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Applies every filter in `filters` to `value`, in order.
+pub fn apply_all(filters: &[Filter], value: String) -> String {
+    filters.iter().fold(value, |value, x| x.apply(&value))
+}
@@ -0,0 +1,548 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Recording what a run did on disk, so that it can be undone later.
+//!
+//! A journal is a small line-based text file, one per run:
+//!
+//! ```text
+//! rena-journal 1
+//! run 1697500000-1234
+//! folder /home/user/images
+//! time 1697500000
+//! plan /home/user/images/a.jpg<TAB>/home/user/images/item_0000000000.jpg
+//! move /home/user/images/a.jpg<TAB>/home/user/images/item_0000000000.jpg
+//! end
+//! undone 1697500100
+//! ```
+//!
+//! Every move the run intends to perform is written up front as a `plan` line,
+//! ending in `<TAB>overwrite` if it replaces a file that is already there.
+//! Every move performed on disk is then appended as it happens, including
+//! moves to temporary names and rollbacks, so replaying the `move` lines always
+//! gives the actual state of the folder. A run without an `end` line was
+//! interrupted. Paths are percent-encoded where needed.
+
+use crate::{execute::Step, RenameItem, RenamePlan};
+use color_eyre::{eyre::eyre, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MAGIC: &str = "rena-journal 1";
+const EXTENSION: &str = "journal";
+
+/// A run, as recorded in its journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Journal {
+    /// Where the journal is stored
+    pub path: PathBuf,
+    /// Unique identifier of the run
+    pub run_id: String,
+    /// Folder the run acted in
+    pub folder: PathBuf,
+    /// When the run started, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Every move the run intended to perform, in order
+    pub planned: Vec<RenameItem>,
+    /// Every move performed on disk, in order
+    pub moves: Vec<RenameItem>,
+    /// Whether the run got to the end of its plan
+    pub finished: bool,
+    /// Whether the run has been undone already
+    pub undone: bool,
+}
+
+impl Journal {
+    /// Reads the journal at the given path.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or isn't a valid journal.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| eyre!("Unable to read journal `{}`: {e}", path.to_string_lossy()))?;
+        let mut lines = text.lines();
+
+        if lines.next() != Some(MAGIC) {
+            return Err(eyre!("`{}` is not a rena journal.", path.to_string_lossy()));
+        }
+
+        let mut journal = Self {
+            path: path.to_path_buf(),
+            run_id: String::new(),
+            folder: PathBuf::new(),
+            timestamp: 0,
+            planned: Vec::new(),
+            moves: Vec::new(),
+            finished: false,
+            undone: false,
+        };
+
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+            match key {
+                "run" => value.clone_into(&mut journal.run_id),
+                "folder" => journal.folder = decode_path(value)?,
+                "time" => journal.timestamp = value.parse()?,
+                "plan" => journal.planned.push(decode_move(value)?),
+                "move" => journal.moves.push(decode_move(value)?),
+                "end" => journal.finished = true,
+                "undone" => journal.undone = true,
+                // Unknown lines are skipped, so older versions can read newer journals.
+                _ => {}
+            }
+        }
+
+        Ok(journal)
+    }
+
+    /// Finds the run with the given identifier in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such run, or its journal is invalid.
+    pub fn find(dir: &Path, run_id: &str) -> Result<Self> {
+        let path = dir.join(format!("{run_id}.{EXTENSION}"));
+
+        if !path.exists() {
+            return Err(eyre!("There is no run `{run_id}` to undo."));
+        }
+
+        Self::load(&path)
+    }
+
+    /// Finds the most recent run in `dir` that hasn't been undone yet, and
+    /// changed anything at all. Runs that were rolled back, or whose renames
+    /// all failed, are passed over.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `dir` can't be read. Invalid journals are skipped.
+    pub fn latest(dir: &Path) -> Result<Option<Self>> {
+        Ok(Self::all(dir)?
+            .into_iter()
+            .filter(|x| !x.undone && !x.net_moves().is_empty())
+            .max_by(|a, b| (a.timestamp, &a.run_id).cmp(&(b.timestamp, &b.run_id))))
+    }
+
+    /// Finds the most recent run in `folder` that was interrupted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `dir` can't be read. Invalid journals are skipped.
+    pub fn interrupted(dir: &Path, folder: &Path) -> Result<Option<Self>> {
+        let folder = absolute(folder)?;
+
+        Ok(Self::all(dir)?
+            .into_iter()
+            .filter(|x| !x.is_finished() && !x.undone && x.folder == folder)
+            .max_by(|a, b| (a.timestamp, &a.run_id).cmp(&(b.timestamp, &b.run_id))))
+    }
+
+    fn all(dir: &Path) -> Result<Vec<Self>> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut journals = Vec::new();
+
+        for entry in dir.read_dir()? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|x| x == EXTENSION) {
+                if let Ok(journal) = Self::load(&path) {
+                    journals.push(journal);
+                }
+            }
+        }
+
+        Ok(journals)
+    }
+
+    /// Whether the run got to the end of its plan.
+    ///
+    /// Journals without a plan are always considered finished.
+    #[must_use]
+    pub const fn is_finished(&self) -> bool {
+        self.finished || self.planned.is_empty()
+    }
+
+    /// Compares the plan of an interrupted run against the recorded moves and
+    /// the disk.
+    ///
+    /// Returns the planned moves that were performed without being recorded
+    /// (because the run was stopped right after performing them), followed by
+    /// the planned moves that still have to be performed.
+    #[must_use]
+    pub fn reconcile(&self) -> (Vec<RenameItem>, Vec<RenameItem>) {
+        let mut recorded: HashMap<(&Path, &Path), isize> = HashMap::new();
+
+        for x in &self.moves {
+            *recorded.entry((&x.original_path, &x.new_path)).or_default() += 1;
+            // A rollback is recorded as the reverse move, and cancels it out.
+            *recorded.entry((&x.new_path, &x.original_path)).or_default() -= 1;
+        }
+
+        let done = self
+            .planned
+            .iter()
+            .map(|x| {
+                recorded
+                    .get(&(x.original_path.as_path(), x.new_path.as_path()))
+                    .is_some_and(|&x| x > 0)
+            })
+            .collect::<Vec<_>>();
+        let next = done.iter().rposition(|&x| x).map_or(0, |x| x + 1);
+
+        let mut unrecorded = Vec::new();
+        let mut remaining = Vec::new();
+
+        for (i, x) in self.planned.iter().enumerate() {
+            if done[i] {
+                continue;
+            }
+
+            // Moves are recorded right after being performed, so only the one
+            // following the last recorded move can have slipped through.
+            if i == next && !x.original_path.exists() && x.new_path.exists() {
+                unrecorded.push(x.clone());
+            } else {
+                remaining.push(x.clone());
+            }
+        }
+
+        (unrecorded, remaining)
+    }
+
+    /// Works out where every item touched by the run started and ended up.
+    ///
+    /// Items that ended up where they started (for example after a rollback)
+    /// are left out. Items inside a directory that was moved afterwards are
+    /// treated as if they had been renamed inside its new location, so moving
+    /// them back doesn't depend on the directory being moved back first.
+    #[must_use]
+    pub fn net_moves(&self) -> Vec<RenameItem> {
+        let mut items: Vec<RenameItem> = Vec::new();
+        let mut locations: HashMap<PathBuf, usize> = HashMap::new();
+        let mut parents: HashSet<PathBuf> = HashSet::new();
+
+        for x in &self.moves {
+            parents.extend(x.new_path.ancestors().skip(1).map(Path::to_path_buf));
+
+            if parents.contains(&x.original_path) {
+                for (i, item) in items.iter_mut().enumerate() {
+                    let Some(rest) = item
+                        .new_path
+                        .strip_prefix(&x.original_path)
+                        .ok()
+                        .filter(|rest| !rest.as_os_str().is_empty())
+                    else {
+                        continue;
+                    };
+                    let moved = x.new_path.join(rest);
+
+                    if let Ok(rest) = item.original_path.strip_prefix(&x.original_path) {
+                        item.original_path = x.new_path.join(rest);
+                    }
+
+                    locations.remove(&item.new_path);
+                    locations.insert(moved.clone(), i);
+                    parents.extend(moved.ancestors().skip(1).map(Path::to_path_buf));
+                    item.new_path = moved;
+                }
+            }
+
+            if let Some(i) = locations.remove(&x.original_path) {
+                items[i].new_path.clone_from(&x.new_path);
+                locations.insert(x.new_path.clone(), i);
+            } else {
+                locations.insert(x.new_path.clone(), items.len());
+                items.push(x.clone());
+            }
+        }
+
+        items.retain(|x| x.original_path != x.new_path);
+        items
+    }
+
+    /// Builds a plan that moves every item of the run back where it was.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing every item that is no longer at the path the
+    /// run left it at, in which case nothing should be moved back.
+    pub fn undo_plan(&self) -> Result<RenamePlan> {
+        let items = self.net_moves();
+        let missing = items
+            .iter()
+            .filter(|x| !x.new_path.exists())
+            .map(|x| format!("`{}`", x.new_path.to_string_lossy()))
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            return Err(eyre!(
+                "Unable to undo run `{}`, these items have moved since: {}",
+                self.run_id,
+                missing.join(", ")
+            ));
+        }
+
+        Ok(RenamePlan {
+            folder: self.folder.clone(),
+            items: items
+                .into_iter()
+                .map(|x| RenameItem {
+                    original_path: x.new_path,
+                    new_path: x.original_path,
+                    ..RenameItem::default()
+                })
+                .collect(),
+            transactional: true,
+            // Items are moved back to where they were, wherever that is.
+            create_dirs: true,
+            ..RenamePlan::default()
+        })
+    }
+
+    /// Records that the run has been undone, so it isn't undone twice.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the journal can't be written to.
+    pub fn mark_undone(&self) -> Result<()> {
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+
+        writeln!(file, "undone {}", now())?;
+
+        Ok(())
+    }
+}
+
+/// Appends moves to a journal as they happen.
+#[derive(Debug)]
+pub(crate) struct JournalWriter {
+    file: File,
+    run_id: String,
+    cwd: PathBuf,
+}
+
+impl JournalWriter {
+    /// Starts a journal for a run in `folder` that is about to perform
+    /// `steps`, inside `dir`.
+    pub(crate) fn create(dir: &Path, folder: &Path, steps: &[Step]) -> Result<Self> {
+        fs::create_dir_all(dir).map_err(|e| {
+            eyre!(
+                "Unable to create journal directory `{}`: {e}",
+                dir.to_string_lossy()
+            )
+        })?;
+
+        let timestamp = now();
+        let base = format!("{timestamp}-{}", process::id());
+        let mut run_id = base.clone();
+        let mut count = 0;
+
+        let mut file = loop {
+            let path = dir.join(format!("{run_id}.{EXTENSION}"));
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    count += 1;
+                    run_id = format!("{base}-{count}");
+                }
+                Err(e) => {
+                    return Err(eyre!(
+                        "Unable to create journal `{}`: {e}",
+                        path.to_string_lossy()
+                    ))
+                }
+            }
+        };
+
+        // Paths are stored absolute, so undoing works from anywhere.
+        let cwd = std::env::current_dir()?;
+
+        writeln!(file, "{MAGIC}")?;
+        writeln!(file, "run {run_id}")?;
+        writeln!(file, "folder {}", encode_path(&absolute(folder)?))?;
+        writeln!(file, "time {timestamp}")?;
+
+        for x in steps {
+            writeln!(
+                file,
+                "plan {}\t{}{}",
+                encode_path(&cwd.join(&x.from)),
+                encode_path(&cwd.join(&x.to)),
+                if x.overwrite { "\toverwrite" } else { "" }
+            )?;
+        }
+
+        file.sync_data()?;
+
+        Ok(Self { file, run_id, cwd })
+    }
+
+    /// Continues the journal of an interrupted run.
+    pub(crate) fn append(journal: &Journal) -> Result<Self> {
+        let file = OpenOptions::new().append(true).open(&journal.path)?;
+
+        Ok(Self {
+            file,
+            run_id: journal.run_id.clone(),
+            cwd: std::env::current_dir()?,
+        })
+    }
+
+    /// The identifier of the run being recorded.
+    pub(crate) fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Records a move that has just been performed.
+    pub(crate) fn record(&mut self, from: &Path, to: &Path) -> Result<()> {
+        writeln!(
+            self.file,
+            "move {}\t{}",
+            encode_path(&self.cwd.join(from)),
+            encode_path(&self.cwd.join(to))
+        )?;
+        // Resuming relies on every performed move being on disk.
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Records that the run has nothing left to do.
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        writeln!(self.file, "end")?;
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+}
+
+/// Where journals are kept by default, usually `~/.local/state/rena`.
+#[must_use]
+pub fn default_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|x| x.join("rena"))
+}
+
+/// Makes `path` absolute, resolving symlinks where possible so the same folder
+/// is always recorded the same way.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    Ok(path
+        .canonicalize()
+        .or_else(|_| std::env::current_dir().map(|x| x.join(path)))?)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+fn path_bytes(path: &Path) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        path.as_os_str().as_bytes().to_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        path.to_string_lossy().as_bytes().to_vec()
+    }
+}
+
+fn encode_path(path: &Path) -> String {
+    let bytes = path_bytes(path);
+    let valid = std::str::from_utf8(&bytes).is_ok();
+    let mut out = Vec::with_capacity(bytes.len());
+
+    for b in bytes {
+        if b == b'%' || b.is_ascii_control() || (!valid && !b.is_ascii()) {
+            out.extend(format!("%{b:02X}").bytes());
+        } else {
+            out.push(b);
+        }
+    }
+
+    String::from_utf8(out).expect("escaped path to be valid UTF-8")
+}
+
+fn decode_move(text: &str) -> Result<RenameItem> {
+    let mut fields = text.split('\t');
+    let (Some(from), Some(to)) = (fields.next(), fields.next()) else {
+        return Err(eyre!("Malformed move in journal: {text}"));
+    };
+
+    Ok(RenameItem {
+        original_path: decode_path(from)?,
+        new_path: decode_path(to)?,
+        overwrite: fields.any(|x| x == "overwrite"),
+    })
+}
+
+fn decode_path(text: &str) -> Result<PathBuf> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = tail
+                .get(..2)
+                .and_then(|x| std::str::from_utf8(x).ok())
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .ok_or_else(|| eyre!("Malformed path in journal: {text}"))?;
+
+            bytes.push(hex);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+
+        Ok(OsString::from_vec(bytes).into())
+    }
+    #[cfg(not(unix))]
+    {
+        String::from_utf8(bytes)
+            .map(|x| OsString::from(x).into())
+            .map_err(|_| eyre!("Malformed path in journal: {text}"))
+    }
+}
@@ -33,22 +33,49 @@ SOFTWARE.
 
 //! Rena is a crate fo bulk renaming of files.
 
+mod atomic;
+mod case;
+mod conflict;
+mod execute;
+mod filter;
+pub mod journal;
+mod limit;
+mod name;
+mod normalize;
+mod plan;
+mod replace;
+mod sanitize;
+mod sort;
+mod template;
 #[cfg(test)]
 mod test;
 
-use clap::{parser::MatchesError, ArgMatches};
+pub use case::Case;
+pub use conflict::{ConflictPolicy, Resolution};
+pub use execute::execute;
+
+use execute::{perform, Step};
+pub use limit::{Limit, Unit};
+pub use normalize::Normalization;
+pub use plan::{plan, Problem, RenameItem, RenamePlan};
+pub use replace::Rule;
+pub use sanitize::{Platform, Sanitized, Sanitizer};
+pub use sort::{natural_cmp, SortOrder};
+pub use template::Template;
+
+use clap::{
+    parser::{MatchesError, ValueSource},
+    ArgMatches,
+};
 use color_eyre::{eyre::eyre, Report, Result};
+use journal::{Journal, JournalWriter};
 use paris::{info, warn};
-use regex::Regex;
-use std::{
-    collections::HashMap,
-    fs::{self, DirEntry},
-    path::PathBuf,
-    string::ToString,
-};
+use regex::bytes::{Regex, RegexBuilder};
+use std::{fs::DirEntry, path::PathBuf};
 
 /// All the arguments after being turned into their respective types.
 #[derive(Debug, Clone, Default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Arguments {
     /// Folder in which to act
     pub folder: PathBuf,
@@ -64,10 +91,84 @@ pub struct Arguments {
     pub padding: usize,
     /// Which direction the number should be padded in
     pub padding_direction: PaddingDirection,
-    /// A Regex to filter input items
+    /// A Regex to filter input items, and to rename them with `match_rename`
     pub match_regex: Option<Regex>,
     /// When renaming, this is used to apply regex capture groups
     pub match_rename: Option<String>,
+    /// Whether `match_rename` replaces every match instead of only the first
+    pub replace_all: bool,
+    /// Substitution rules applied to every name in order, after
+    /// `match_rename`
+    pub rules: Vec<Rule>,
+    /// Whether to not actually execute any rename operations
+    pub dry_run: bool,
+    /// Whether to undo every completed rename if any of them fails
+    pub transactional: bool,
+    /// Directory to write an undo journal to, if any
+    pub journal: Option<PathBuf>,
+    /// What to do when an item's new name is already taken
+    pub on_conflict: ConflictPolicy,
+    /// Order in which items are numbered
+    pub sort: SortOrder,
+    /// Whether to reverse the sort order
+    pub reverse: bool,
+    /// Whether to descend into subdirectories
+    pub recursive: bool,
+    /// How deep an item has to be to be renamed, where `1` is the folder's
+    /// immediate children
+    pub min_depth: usize,
+    /// How deep to descend at most, if `recursive` is set
+    pub max_depth: Option<usize>,
+    /// Whether numbering continues across directories or restarts in each
+    pub counter: CounterScope,
+    /// Template for new names, instead of the prefix and the padded number
+    pub template: Option<Template>,
+    /// Whether new names may move items into subdirectories, which are
+    /// created as needed
+    pub create_dirs: bool,
+    /// How to make new names valid on other platforms, if at all
+    pub sanitize: Option<Sanitizer>,
+    /// The longest a new name can be, if there is a limit
+    pub max_length: Option<Limit>,
+    /// Case to convert names to, instead of the prefix and the padded number
+    pub case: Option<Case>,
+    /// Whether `case` applies to extensions as well
+    pub case_extension: bool,
+    /// What to replace characters that can't be converted to ASCII with, if
+    /// names are converted to ASCII
+    pub transliterate: Option<String>,
+    /// Separator between words, if names are turned into slugs
+    pub slug: Option<String>,
+    /// Unicode normalisation form that names are matched in and converted
+    /// to, if any, instead of the prefix and the padded number unless there's
+    /// a template
+    pub normalize: Option<Normalization>,
+}
+
+/// All the arguments of the `undo` subcommand.
+#[derive(Debug, Clone, Default)]
+pub struct UndoArguments {
+    /// Directory the journals are kept in
+    pub journal: PathBuf,
+    /// The run to undo, or the most recent one if `None`
+    pub run: Option<String>,
+    /// Whether to output more logging information
+    pub verbose: bool,
+    /// Whether to not actually execute any rename operations
+    pub dry_run: bool,
+}
+
+/// All the arguments of the `resume` subcommand.
+#[derive(Debug, Clone, Default)]
+pub struct ResumeArguments {
+    /// Directory the journals are kept in
+    pub journal: PathBuf,
+    /// Folder in which a run was interrupted
+    pub folder: PathBuf,
+    /// Whether to roll the run back instead of finishing it
+    pub rollback: bool,
+    /// Whether to output more logging information
+    pub verbose: bool,
     /// Whether to not actually execute any rename operations
     pub dry_run: bool,
 }
@@ -114,16 +215,34 @@ impl From<String> for PaddingDirection {
     }
 }
 
-/// Just an intermediary struct to contain some data.
-#[derive(Debug, Clone)]
-struct RenameItem {
-    pub original_path: PathBuf,
-    pub new_path: PathBuf,
+/// How far a counter runs before it starts over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CounterScope {
+    /// One counter for the whole run
+    #[default]
+    Global,
+    /// A new counter for every directory
+    Directory,
+}
+
+impl From<&String> for CounterScope {
+    fn from(a: &String) -> Self {
+        let a = a.to_lowercase();
+
+        match a.as_ref() {
+            "global" => Self::Global,
+            "directory" | "dir" | "per-directory" => Self::Directory,
+            _ => unreachable!(
+                "If this is reached, something in validation has gone *horribly* wrong."
+            ),
+        }
+    }
 }
 
 impl TryFrom<ArgMatches> for Arguments {
     type Error = Report;
 
+    #[allow(clippy::too_many_lines)]
     fn try_from(a: ArgMatches) -> Result<Self, Self::Error> {
         let folder = a
             .get_one::<PathBuf>("folder")
@@ -143,7 +262,7 @@ impl TryFrom<ArgMatches> for Arguments {
             .get_one::<usize>("padding")
             .copied()
             .ok_or_else(|| Report::msg("Unable to turn 'padding' argument into usize"))?;
-        let padding_direction = match a.try_get_one::<String>("padding_direction") {
+        let padding_direction = match a.try_get_one::<String>("padding-direction") {
             // For some reason the default wasn't working here so I removed it and made it manually default
             Ok(value) => value.map_or_else(PaddingDirection::default, PaddingDirection::from),
             Err(e) => match e {
@@ -152,7 +271,15 @@ impl TryFrom<ArgMatches> for Arguments {
             },
         };
         let match_regex = match a.try_get_one::<String>("match") {
-            Ok(Some(regex)) => Some(Regex::new(regex)?),
+            Ok(Some(regex)) => Some(
+                RegexBuilder::new(regex)
+                    .case_insensitive(a.get_flag("ignore-case"))
+                    .multi_line(a.get_flag("multi-line"))
+                    .dot_matches_new_line(a.get_flag("dot-all"))
+                    .ignore_whitespace(a.get_flag("ignore-whitespace"))
+                    .unicode(!a.get_flag("ascii"))
+                    .build()?,
+            ),
             Ok(None) => None,
             Err(e) => {
                 return Err(Report::msg(format!("Invalid `--match` argument: {e}")));
@@ -167,7 +294,120 @@ impl TryFrom<ArgMatches> for Arguments {
                 )));
             }
         };
+        let replace_all = a.get_flag("all");
+        let rules = match a.try_get_many::<String>("rule") {
+            Ok(Some(rules)) => rules.map(|x| Rule::parse(x)).collect::<Result<Vec<_>>>()?,
+            Ok(None) => Vec::new(),
+            Err(e) => return Err(Report::msg(format!("Invalid `--rule` argument: {e}"))),
+        };
         let dry_run = a.get_flag("dry-run");
+        let transactional = a.get_flag("transactional");
+        let sort = match a.try_get_one::<String>("sort") {
+            Ok(value) => value.map_or_else(SortOrder::default, SortOrder::from),
+            Err(e) => return Err(Report::msg(format!("Invalid `--sort` argument: {e}"))),
+        };
+        let reverse = a.get_flag("reverse");
+        let on_conflict = match a.try_get_one::<String>("on-conflict") {
+            Ok(value) => value.map_or_else(ConflictPolicy::default, ConflictPolicy::from),
+            Err(e) => {
+                return Err(Report::msg(format!(
+                    "Invalid `--on-conflict` argument: {e}"
+                )))
+            }
+        };
+        let recursive = a.get_flag("recursive");
+        let min_depth = a.get_one::<usize>("min-depth").copied().unwrap_or(1);
+        let max_depth = a.get_one::<usize>("max-depth").copied();
+
+        if max_depth.is_some_and(|max| min_depth > max) {
+            return Err(Report::msg(
+                "`--min-depth` can't be greater than `--max-depth`.",
+            ));
+        }
+
+        let counter = match a.try_get_one::<String>("counter") {
+            Ok(value) => value.map_or_else(CounterScope::default, CounterScope::from),
+            Err(e) => return Err(Report::msg(format!("Invalid `--counter` argument: {e}"))),
+        };
+        let template = match a.try_get_one::<String>("template") {
+            Ok(value) => value.map(|x| Template::parse(x)).transpose()?,
+            Err(e) => return Err(Report::msg(format!("Invalid `--template` argument: {e}"))),
+        };
+        let create_dirs = a.get_flag("create-dirs");
+        let mut sanitize = match a.try_get_one::<String>("sanitize") {
+            Ok(value) => value.map(|x| Sanitizer::new(Platform::from(x))),
+            Err(e) => return Err(Report::msg(format!("Invalid `--sanitize` argument: {e}"))),
+        };
+
+        if let Some(sanitize) = &mut sanitize {
+            match a.try_get_many::<String>("sanitize-with") {
+                Ok(Some(values)) => {
+                    for x in values {
+                        sanitize.add_replacement(x)?;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    return Err(Report::msg(format!(
+                        "Invalid `--sanitize-with` argument: {e}"
+                    )))
+                }
+            }
+        }
+
+        let case = match a.try_get_one::<String>("case") {
+            Ok(value) => value.map(Case::from),
+            Err(e) => return Err(Report::msg(format!("Invalid `--case` argument: {e}"))),
+        };
+        let case_extension = a.get_flag("case-extension");
+        let transliterate = match a.try_get_one::<String>("transliterate") {
+            Ok(value) => value.cloned(),
+            Err(e) => {
+                return Err(Report::msg(format!(
+                    "Invalid `--transliterate` argument: {e}"
+                )))
+            }
+        };
+        let slug = match a.try_get_one::<String>("slug") {
+            Ok(value) => value.cloned(),
+            Err(e) => return Err(Report::msg(format!("Invalid `--slug` argument: {e}"))),
+        };
+        let normalize = match a.try_get_one::<String>("normalize") {
+            Ok(value) => value.map(Normalization::from),
+            Err(e) => return Err(Report::msg(format!("Invalid `--normalize` argument: {e}"))),
+        };
+
+        // Without a template, `--match-rename` or rules, `--normalize` takes
+        // the place of the numbering.
+        if normalize.is_some()
+            && template.is_none()
+            && match_rename.is_none()
+            && rules.is_empty()
+            && ["prefix", "padding", "padding-direction", "origin"]
+                .into_iter()
+                .any(|x| a.value_source(x) == Some(ValueSource::CommandLine))
+        {
+            return Err(Report::msg(
+                "`--normalize` can't be used with `--prefix`, `--padding`, `--padding-direction` or `--origin` unless `--template`, `--match-rename` or `--rule` is used as well.",
+            ));
+        }
+        let max_length = match a.try_get_one::<String>("max-length") {
+            Ok(Some(value)) => Some(Limit {
+                ellipsis: a.get_one::<String>("ellipsis").cloned().unwrap_or_default(),
+                ..Limit::parse(value).ok_or_else(|| {
+                    Report::msg(format!(
+                        "Invalid `--max-length` argument: `{value}` isn't a length such as `255` or `80c`."
+                    ))
+                })?
+            }),
+            Ok(None) => None,
+            Err(e) => return Err(Report::msg(format!("Invalid `--max-length` argument: {e}"))),
+        };
+        let journal = journal::default_dir();
+
+        if journal.is_none() {
+            warn!("Unable to find a directory for journals, this run can't be undone.");
+        }
 
         Ok(Self {
             folder,
@@ -179,6 +419,69 @@ impl TryFrom<ArgMatches> for Arguments {
             padding_direction,
             match_regex,
             match_rename,
+            replace_all,
+            rules,
+            dry_run,
+            transactional,
+            journal,
+            on_conflict,
+            sort,
+            reverse,
+            recursive,
+            min_depth,
+            max_depth,
+            counter,
+            template,
+            create_dirs,
+            sanitize,
+            max_length,
+            case,
+            case_extension,
+            transliterate,
+            slug,
+            normalize,
+        })
+    }
+}
+
+impl TryFrom<ArgMatches> for UndoArguments {
+    type Error = Report;
+
+    fn try_from(a: ArgMatches) -> Result<Self, Self::Error> {
+        let journal = journal::default_dir()
+            .ok_or_else(|| Report::msg("Unable to find the directory journals are kept in"))?;
+        let run = a.get_one::<String>("run").cloned();
+        let verbose = a.get_flag("verbose");
+        let dry_run = a.get_flag("dry-run");
+
+        Ok(Self {
+            journal,
+            run,
+            verbose,
+            dry_run,
+        })
+    }
+}
+
+impl TryFrom<ArgMatches> for ResumeArguments {
+    type Error = Report;
+
+    fn try_from(a: ArgMatches) -> Result<Self, Self::Error> {
+        let journal = journal::default_dir()
+            .ok_or_else(|| Report::msg("Unable to find the directory journals are kept in"))?;
+        let folder = a
+            .get_one::<PathBuf>("folder")
+            .cloned()
+            .ok_or_else(|| Report::msg("Unable to turn 'folder' argument into path"))?;
+        let rollback = a.get_flag("rollback");
+        let verbose = a.get_flag("verbose");
+        let dry_run = a.get_flag("dry-run");
+
+        Ok(Self {
+            journal,
+            folder,
+            rollback,
+            verbose,
             dry_run,
         })
     }
@@ -186,6 +489,8 @@ impl TryFrom<ArgMatches> for Arguments {
 
 /// Runs rena with the given arguments.
 ///
+/// This is a shorthand for [`plan()`] followed by [`execute()`], logging any
+/// problems found along the way.
 ///
 /// # Errors
 ///
@@ -194,215 +499,179 @@ impl TryFrom<ArgMatches> for Arguments {
 /// - The target doesn't exist
 /// - The target is not a directory
 /// - We can't read the directory's contents
-///
-/// # Panics
-///
-/// We currently verify that the result of [`read_dir()`] is not `Err` before
-/// unwrapping it, so this shouldn't ever panic.
+/// - The plan has collisions, in which case nothing is renamed
+#[allow(clippy::needless_pass_by_value)] // Kept by value for API compatibility
 pub fn run(args: Arguments) -> Result<()> {
-    if !args.folder.exists() {
-        return Err(eyre!(format!(
-            "Folder `{}` does not exist.",
-            args.folder.to_string_lossy()
-        )));
+    let plan = plan(&args)?;
+
+    if !plan.unchanged.is_empty() {
+        info!(
+            "{} item(s) already have their new name, and are left alone.",
+            plan.unchanged.len()
+        );
     }
 
-    if !args.folder.is_dir() {
-        return Err(eyre!(format!(
-            "`{}` is not a folder.",
-            args.folder.to_string_lossy()
-        )));
+    for x in &plan.sanitized {
+        info!("{}", x);
     }
 
-    let read = args.folder.read_dir();
+    if let Some(sanitize) = args
+        .sanitize
+        .as_ref()
+        .filter(|_| !plan.sanitized.is_empty())
+    {
+        info!(
+            "{} name(s) were changed to be valid on {}.",
+            plan.sanitized.len(),
+            sanitize.platform
+        );
+    }
 
-    if let Err(e) = read {
-        return Err(eyre!(format!(
-            "Unable to read directory {}: {}",
-            args.folder.to_string_lossy(),
-            e
-        )));
+    for resolution in &plan.resolutions {
+        warn!("{}", resolution);
     }
-    let read = read.expect("Failed to read directory");
 
-    let items = match &args.match_regex {
-        Some(r) => filter_items_regex(read, args.directory, r),
-        None => filter_items(read, args.directory),
-    };
+    if !plan.resolutions.is_empty() {
+        let count = |f: fn(&Resolution) -> bool| plan.resolutions.iter().filter(|x| f(x)).count();
 
-    if args.match_rename.is_some() {
-        rename_regex(&items, args);
-    } else {
-        rename_normal(&items, args);
+        info!(
+            "Resolved {} conflict(s): {} skipped, {} overwritten, {} suffixed.",
+            plan.resolutions.len(),
+            count(|x| matches!(x, Resolution::Skipped(_))),
+            count(|x| matches!(x, Resolution::Overwritten(_))),
+            count(|x| matches!(x, Resolution::Suffixed { .. })),
+        );
     }
 
-    Ok(())
+    for problem in &plan.problems {
+        warn!("{}", problem);
+    }
+
+    execute(plan)
 }
 
-// Janky, but it works. I think. We'll see, hopefully.
-fn rename_normal(items: &[PathBuf], args: Arguments) {
-    let verbose = args.verbose;
-    let fmt = match args.padding_direction {
-        PaddingDirection::Left => {
-            "{folder}/{prefix}_{number:0>NUM}{ext}".replace("NUM", &format!("{}", args.padding))
-        }
-        PaddingDirection::Right => {
-            "{folder}/{prefix}_{number:0<NUM}{ext}".replace("NUM", &format!("{}", args.padding))
-        }
-        PaddingDirection::Middle => {
-            "{folder}/{prefix}_{number:0|NUM}{ext}".replace("NUM", &format!("{}", args.padding))
-        }
+/// Moves every item of a previous run back to where it was.
+///
+/// Before anything is moved, every item is checked to still be where the run
+/// left it. The journal is then marked as undone, so the same run isn't
+/// undone twice.
+///
+/// # Errors
+///
+/// Returns an error in the following circumstances:
+///
+/// - There is no such run, or no run that changed anything
+/// - The run has already been undone, or didn't change anything
+/// - Some items have moved since the run
+/// - Moving the items back fails, in which case the undo itself is rolled back
+pub fn undo(args: &UndoArguments) -> Result<()> {
+    let journal = match &args.run {
+        Some(run) => Journal::find(&args.journal, run)?,
+        None => Journal::latest(&args.journal)?.ok_or_else(|| eyre!("There is no run to undo."))?,
     };
 
-    let fmt = if args.directory { fmt + "/" } else { fmt };
+    if journal.undone {
+        return Err(eyre!("Run `{}` has already been undone.", journal.run_id));
+    }
 
-    let mut count = args.origin;
+    if !journal.is_finished() {
+        return Err(eyre!(
+            "Run `{}` was interrupted, use `rena resume` to finish or roll it back.",
+            journal.run_id
+        ));
+    }
 
-    let mut map = HashMap::new();
+    let mut plan = journal.undo_plan()?;
 
-    map.insert(
-        "folder".to_string(),
-        args.folder.to_string_lossy().to_string(),
-    );
-    map.insert("prefix".to_string(), args.prefix);
+    if plan.items.is_empty() {
+        return Err(eyre!(
+            "Run `{}` didn't change anything, so there is nothing to undo.",
+            journal.run_id
+        ));
+    }
 
-    let items = items
-        .iter()
-        .map(|x| {
-            let ext = x
-                .extension()
-                .map_or_else(String::new, |x| format!(".{}", x.to_string_lossy()));
-            map.insert("number".to_string(), format!("{count}"));
-            map.insert("ext".to_string(), ext);
-            count += 1;
-
-            RenameItem {
-                original_path: x.clone(),
-                new_path: strfmt::strfmt(&fmt, &map)
-                    .expect("String formatting failed")
-                    .into(),
-            }
-        })
-        .filter(|x| {
-            if x.new_path.exists() {
-                warn!(
-                    "File `{}` already exists, unable to rename.",
-                    x.new_path.to_string_lossy()
-                );
-                false
-            } else {
-                true
-            }
-        })
-        .collect::<Vec<RenameItem>>();
-
-    let dry_run = args.dry_run;
-    for x in items {
-        if x.new_path.exists() {
-            warn!(
-                "Item `{}` already exists, unable to rename.",
-                x.new_path.to_string_lossy()
-            );
-            return;
-        }
-        if dry_run {
-            info!(
-                "[DRY RUN]: `{}` -> `{}`",
-                x.original_path.to_string_lossy(),
-                x.new_path.to_string_lossy()
-            );
-        } else {
-            match fs::rename(&x.original_path, &x.new_path) {
-                Ok(()) => {
-                    if verbose {
-                        info!(
-                            "[DONE] `{}` -> `{}`",
-                            x.original_path.to_string_lossy(),
-                            x.new_path.to_string_lossy()
-                        );
-                    }
-                }
-                Err(e) => warn!(
-                    "[FAIL] `{}` -> `{}`: {}",
-                    x.original_path.to_string_lossy(),
-                    x.new_path.to_string_lossy(),
-                    e
-                ),
-            }
-        }
+    plan.dry_run = args.dry_run;
+    plan.verbose = args.verbose;
+
+    for problem in plan.validate() {
+        warn!("{}", problem);
+    }
+
+    execute(plan)?;
+
+    if !args.dry_run {
+        journal.mark_undone()?;
     }
+
+    Ok(())
 }
 
-fn rename_regex(items: &[PathBuf], args: Arguments) {
-    let verbose = args.verbose;
+/// Finishes, or rolls back, a run that was interrupted in the given folder.
+///
+/// The run's journal is compared against the disk first, so moves that were
+/// performed but not recorded aren't performed twice.
+///
+/// # Errors
+///
+/// Returns an error in the following circumstances:
+///
+/// - There is no interrupted run in the folder
+/// - The journal can't be written to
+/// - Finishing or rolling back the run fails
+pub fn resume(args: &ResumeArguments) -> Result<()> {
+    let mut journal = Journal::interrupted(&args.journal, &args.folder)?.ok_or_else(|| {
+        eyre!(
+            "There is no interrupted run in `{}`.",
+            args.folder.to_string_lossy()
+        )
+    })?;
+    let (unrecorded, remaining) = journal.reconcile();
+    let mut writer = if args.dry_run {
+        None
+    } else {
+        Some(JournalWriter::append(&journal)?)
+    };
 
-    let regex = args.match_regex.expect("Regex is None");
-    let match_rename = args.match_rename.expect("Match rename is None");
-    let items = items
-        .iter()
-        .map(|x| {
-            let text = x
-                .file_name()
-                .expect("there to be a filename")
-                .to_string_lossy();
-            let after = regex.replace(&text, match_rename.as_str()).to_string();
-            let mut new_x = x.clone();
-
-            new_x.set_file_name(after);
-
-            RenameItem {
-                original_path: x.clone(),
-                new_path: new_x,
-            }
-        })
-        .filter(|x| {
-            if x.new_path.exists() {
-                warn!(
-                    "Item `{}` already exists, unable to rename.",
-                    x.new_path.to_string_lossy()
-                );
-                false
-            } else {
-                true
-            }
-        })
-        .collect::<Vec<RenameItem>>();
-
-    let dry_run = args.dry_run;
-    for x in items {
-        if x.new_path.exists() {
-            warn!(
-                "Item `{}` already exists, unable to rename.",
-                x.new_path.to_string_lossy()
-            );
-            return;
+    for x in unrecorded {
+        if let Some(writer) = &mut writer {
+            writer.record(&x.original_path, &x.new_path)?;
         }
-        if dry_run {
-            info!(
-                "[DRY RUN]: `{}` -> `{}`",
-                x.original_path.to_string_lossy(),
-                x.new_path.to_string_lossy()
-            );
-        } else {
-            match fs::rename(&x.original_path, &x.new_path) {
-                Ok(()) => {
-                    if verbose {
-                        info!(
-                            "[DONE] `{}` -> `{}`",
-                            x.original_path.to_string_lossy(),
-                            x.new_path.to_string_lossy()
-                        );
-                    }
-                }
-                Err(e) => warn!(
-                    "[FAIL] `{}` -> `{}`: {}",
-                    x.original_path.to_string_lossy(),
-                    x.new_path.to_string_lossy(),
-                    e
-                ),
-            }
+        journal.moves.push(x);
+    }
+
+    if args.rollback {
+        let mut plan = journal.undo_plan()?;
+
+        plan.dry_run = args.dry_run;
+        plan.verbose = args.verbose;
+
+        execute(plan)?;
+
+        if let Some(mut writer) = writer {
+            writer.finish()?;
+            journal.mark_undone()?;
         }
+
+        return Ok(());
     }
+
+    let plan = RenamePlan {
+        folder: journal.folder.clone(),
+        dry_run: args.dry_run,
+        verbose: args.verbose,
+        create_dirs: true,
+        ..RenamePlan::default()
+    };
+    let steps = remaining
+        .into_iter()
+        .map(|x| Step {
+            from: x.original_path,
+            to: x.new_path,
+            overwrite: x.overwrite,
+        })
+        .collect();
+
+    perform(steps, &plan, writer)
 }
 
 fn filter_items<I>(read: I, dir: bool) -> Vec<PathBuf>
@@ -445,7 +714,12 @@ where
         .collect::<Vec<PathBuf>>()
 }
 
-fn filter_items_regex<I>(read: I, dir: bool, regex: &Regex) -> Vec<PathBuf>
+fn filter_items_regex<I>(
+    read: I,
+    dir: bool,
+    regex: &Regex,
+    normalize: Option<Normalization>,
+) -> Vec<PathBuf>
 where
     I: Iterator<Item = std::io::Result<DirEntry>>,
 {
@@ -457,16 +731,22 @@ where
         Ok(item) => {
             let item_type = item.file_type();
             let item_name = item.file_name();
-            let item_name = item_name.to_string_lossy();
 
             if let Err(e) = item_type {
-                warn!("Unable to get filetype of {}: {}", item_name, e);
+                warn!(
+                    "Unable to get filetype of {}: {}",
+                    item_name.to_string_lossy(),
+                    e
+                );
                 return false;
             }
 
             let item_type = item_type.expect("item_type is None");
 
-            regex.is_match(&item_name)
+            let item_name = name::to_bytes(&item_name);
+            let normalized = normalize.and_then(|x| x.apply_bytes(item_name));
+
+            regex.is_match(normalized.as_deref().unwrap_or(item_name))
                 && if dir {
                     item_type.is_dir()
                 } else {
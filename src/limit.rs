@@ -0,0 +1,161 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Keeping new names short enough.
+
+use crate::name;
+use std::ffi::{OsStr, OsString};
+
+/// What the length of a name is counted in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Unit {
+    /// Bytes, as most filesystems limit names to
+    #[default]
+    Bytes,
+    /// Characters
+    Chars,
+}
+
+/// The longest a new name can be, as given with `--max-length`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limit {
+    /// The longest a name can be
+    pub length: usize,
+    /// What `length` is counted in
+    pub unit: Unit,
+    /// Marker put where a name was cut short, if any
+    pub ellipsis: String,
+}
+
+impl Limit {
+    /// Parses a length such as `255`, `255b` (both in bytes) or `80c` (in
+    /// characters), without an ellipsis.
+    #[must_use]
+    pub fn parse(spec: &str) -> Option<Self> {
+        let unit = if spec.ends_with('c') {
+            Unit::Chars
+        } else {
+            Unit::Bytes
+        };
+        let length = spec.strip_suffix(['b', 'c']).unwrap_or(spec);
+
+        Some(Self {
+            length: length.parse().ok().filter(|&x| x > 0)?,
+            unit,
+            ellipsis: String::new(),
+        })
+    }
+
+    /// Cuts `name` short if it's too long, or if a `tag` has to be added to
+    /// tell it apart from another name.
+    ///
+    /// The end of the stem is cut, at a character boundary, and the ellipsis
+    /// and `tag` are put in its place. The extension and any number at the
+    /// end of the stem, such as a counter, are kept, unless they leave no room
+    /// for the stem, and the ellipsis is left out if it doesn't fit. Returns
+    /// `None` if nothing has to change, or `tag` alone is too long.
+    #[must_use]
+    pub fn truncate(&self, name: &OsStr, tag: &str) -> Option<OsString> {
+        let bytes = name::to_bytes(name);
+
+        if tag.is_empty() && self.measure(bytes) <= self.length
+            || self.measure(tag.as_bytes()) > self.length
+        {
+            return None;
+        }
+
+        let extension = bytes
+            .iter()
+            .rposition(|&x| x == b'.')
+            .filter(|&i| i > 0)
+            .unwrap_or(bytes.len());
+        let (stem, extension) = bytes.split_at(extension);
+        let digits = stem.len()
+            - stem
+                .iter()
+                .rposition(|x| !x.is_ascii_digit())
+                .map_or(0, |i| i + 1);
+        let (head, number) = stem.split_at(stem.len() - digits);
+        let kept = [tag.as_bytes(), number, extension].concat();
+        // A name that would start with its extension is hidden.
+        let full = if kept.starts_with(b".") {
+            self.measure(&kept) >= self.length
+        } else {
+            self.measure(&kept) > self.length
+        };
+        let (head, kept) = if full {
+            // Nothing would be left over for the rest of the name.
+            (bytes, tag.as_bytes().to_vec())
+        } else {
+            (head, kept)
+        };
+        let budget = self.length - self.measure(&kept);
+        let mut result = if self.measure(head) <= budget {
+            head.to_vec()
+        } else {
+            let ellipsis = self.ellipsis.as_bytes();
+            let ellipsis = if self.measure(ellipsis) < budget {
+                ellipsis
+            } else {
+                &[]
+            };
+
+            [self.cut(head, budget - self.measure(ellipsis)), ellipsis].concat()
+        };
+
+        result.extend_from_slice(&kept);
+        name::from_bytes(result).ok()
+    }
+
+    /// Length of `bytes` in the limit's unit.
+    fn measure(&self, bytes: &[u8]) -> usize {
+        match self.unit {
+            Unit::Bytes => bytes.len(),
+            Unit::Chars => bytes.iter().filter(|&&x| !is_continuation(x)).count(),
+        }
+    }
+
+    /// The longest start of `bytes` that is at most `length` long, without
+    /// cutting a character in half.
+    fn cut<'a>(&self, bytes: &'a [u8], length: usize) -> &'a [u8] {
+        let mut end = match self.unit {
+            Unit::Bytes => length.min(bytes.len()),
+            Unit::Chars => bytes
+                .iter()
+                .enumerate()
+                .filter(|&(_, &x)| !is_continuation(x))
+                .nth(length)
+                .map_or(bytes.len(), |(i, _)| i),
+        };
+
+        while end > 0 && end < bytes.len() && is_continuation(bytes[end]) {
+            end -= 1;
+        }
+
+        &bytes[..end]
+    }
+}
+
+/// Whether `byte` continues a character started by an earlier byte.
+const fn is_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
@@ -35,7 +35,8 @@ SOFTWARE.
 
 use clap::{
     builder::{NonEmptyStringValueParser, PossibleValuesParser, ValueParser},
-    crate_authors, crate_description, crate_version, Arg, ArgAction, Command, ValueHint,
+    crate_authors, crate_description, crate_version, value_parser, Arg, ArgAction, Command,
+    ValueHint,
 };
 use color_eyre::{config::HookBuilder, Result};
 use paris::{error, info};
@@ -51,20 +52,90 @@ fn main() -> Result<()> {
     let matches = app.get_matches();
 
     info!("Starting execution...");
-    match rena::run(matches.try_into()?) {
+    let result = match matches.subcommand() {
+        Some(("undo", undo)) => rena::undo(&undo.clone().try_into()?),
+        Some(("resume", resume)) => rena::resume(&resume.clone().try_into()?),
+        _ => rena::run(matches.try_into()?),
+    };
+
+    match result {
         Ok(()) => info!("Completed successfully!"),
         Err(e) => error!("Encountered an error: {}", e),
     }
     Ok(())
 }
 
-#[allow(clippy::too_many_lines)]
+#[allow(clippy::too_many_lines, clippy::literal_string_with_formatting_args)] // Help texts show templates
 fn build_app() -> Command {
     Command::new("rena")
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
         .after_help("For more information, visit <https://github.com/lyssieth/rena>")
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("undo")
+                .about("Moves every item of a previous run back to where it was.")
+                .arg(
+                    Arg::new("run")
+                        .help("ID of the run to undo. Defaults to the most recent one.")
+                        .index(1)
+                        .required(false)
+                        .action(ArgAction::Set)
+                        .value_name("RUN")
+                        .value_parser(ValueParser::new(NonEmptyStringValueParser::new()))
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .help("Turns on some (potentially) annoying logging for more verbose output.")
+                        .action(ArgAction::SetTrue)
+                        .long("verbose")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .help("Disables performing actual renaming.")
+                        .action(ArgAction::SetTrue)
+                        .long("dry-run")
+                        .required(false),
+                )
+        )
+        .subcommand(
+            Command::new("resume")
+                .about("Finishes, or rolls back, a run that was interrupted.")
+                .arg(
+                    Arg::new("folder")
+                        .help("Path to the folder the run was interrupted in")
+                        .index(1)
+                        .required(true)
+                        .value_hint(ValueHint::DirPath)
+                        .action(ArgAction::Set)
+                        .value_name("FOLDER")
+                        .value_parser(ValueParser::path_buf())
+                )
+                .arg(
+                    Arg::new("rollback")
+                        .help("Moves everything back instead of finishing the run.")
+                        .action(ArgAction::SetTrue)
+                        .long("rollback")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .help("Turns on some (potentially) annoying logging for more verbose output.")
+                        .action(ArgAction::SetTrue)
+                        .long("verbose")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .help("Disables performing actual renaming.")
+                        .action(ArgAction::SetTrue)
+                        .long("dry-run")
+                        .required(false),
+                )
+        )
         .arg(
             Arg::new("folder")
                 .help("Path to the folder containing items")
@@ -79,8 +150,6 @@ fn build_app() -> Command {
             Arg::new("directory")
                 .help("Causes the app to act on directories instead of files.")
                 .action(ArgAction::SetTrue)
-                .value_hint(ValueHint::Other)
-                .value_parser(ValueParser::bool())
                 .long("dir")
                 .required(false)
         )
@@ -88,8 +157,6 @@ fn build_app() -> Command {
             Arg::new("verbose")
                 .help("Turns on some (potentially) annoying logging for more verbose output.")
                 .action(ArgAction::SetTrue)
-                .value_hint(ValueHint::Other)
-                .value_parser(ValueParser::bool())
                 .long("verbose")
                 .required(false),
         )
@@ -102,7 +169,7 @@ fn build_app() -> Command {
                 .default_value("0")
                 .action(ArgAction::Set)
                 .default_missing_value("0")
-                .value_parser(ValueParser::new(NonEmptyStringValueParser::new()))
+                .value_parser(value_parser!(usize))
                 .value_hint(ValueHint::Other)
                 .value_name("INDEX")
                 .use_value_delimiter(false)
@@ -128,7 +195,7 @@ fn build_app() -> Command {
                 .action(ArgAction::Set)
                 .required(false)
                 .default_value("10")
-                .value_parser(ValueParser::new(NonEmptyStringValueParser::new()))
+                .value_parser(value_parser!(usize))
                 .default_missing_value("10")
                 .value_name("PADDING")
                 .value_hint(ValueHint::Other)
@@ -144,6 +211,210 @@ fn build_app() -> Command {
             .action(ArgAction::Set)
             .use_value_delimiter(false)
         )
+        .arg(
+            Arg::new("template")
+                .help("Template for new names, instead of `--prefix` and `--padding`. See `--help` for more info.")
+                .long_help(
+                    "Template for new names, instead of `--prefix` and `--padding`.
+                    Variables in braces are replaced for every item: `{n}` (its number),
+                    `{prefix}`, `{name}`, `{stem}` (the name without extension), `{ext}`
+                    (the extension, with its dot), `{parent}` (the directory's name),
+                    `{size}` (in bytes) and `{mtime}` (last modification time).
+                    A format can follow a `:`, as in `{n:03}`, `{stem:_<20}` or
+                    `{mtime:%Y%m%d}`, and filters can follow a `|`, as in
+                    `{stem|lower|truncate:40}` (see `--match-rename`).
+                    Use `{{` and `}}` for literal braces.",
+                )
+                .short('t')
+                .long("template")
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(ValueParser::new(NonEmptyStringValueParser::new()))
+                .value_hint(ValueHint::Other)
+                .value_name("TEMPLATE")
+                .conflicts_with_all(["match-rename", "rule"])
+                .use_value_delimiter(false)
+        )
+        .arg(
+            Arg::new("sanitize")
+                .help("Makes new names valid on the given platform.")
+                .long_help(
+                    "Makes new names valid on the given platform, and lists every name that had to be changed.
+                    Characters that aren't allowed are replaced with `_` (see `--sanitize-with`).
+                    On Windows, dots and spaces at the end are removed, and device names such as
+                    `CON` get `_` after them. Names longer than 255 bytes are cut short before their
+                    extension. `portable` is valid on all of `posix`, `windows` and `macos`.",
+                )
+                .long("sanitize")
+                .required(false)
+                .value_parser(PossibleValuesParser::new(["posix", "windows", "macos", "portable"]))
+                .value_hint(ValueHint::Other)
+                .value_name("PLATFORM")
+                .action(ArgAction::Set)
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("sanitize-with")
+                .help("What `--sanitize` replaces characters with. `C=REPLACEMENT` only applies to the character `C`.")
+                .long("sanitize-with")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_hint(ValueHint::Other)
+                .value_name("REPLACEMENT")
+                .requires("sanitize")
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("max-length")
+                .help("Longest a new name can be, in bytes, or in characters if followed by `c`, as in `80c`.")
+                .long_help(
+                    "Longest a new name can be, in bytes, or in characters if followed by `c`, as in `80c`.
+                    Longer names are cut short at the end of their stem, keeping the extension and any
+                    number at the end of the stem, such as a counter. Names that only become the same
+                    when they are cut get a `~1`, `~2`, ... tag to tell them apart.",
+                )
+                .long("max-length")
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(ValueParser::new(NonEmptyStringValueParser::new()))
+                .value_hint(ValueHint::Other)
+                .value_name("LENGTH")
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("ellipsis")
+                .help("Marks where `--max-length` cut a name short, with `…` unless given something else.")
+                .long("ellipsis")
+                .required(false)
+                .action(ArgAction::Set)
+                .num_args(0..=1)
+                .default_missing_value("…")
+                .value_hint(ValueHint::Other)
+                .value_name("MARKER")
+                .requires("max-length")
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("create-dirs")
+                .help("Allows new names to move items into subdirectories, which are created as needed.")
+                .long_help(
+                    "Allows new names to move items into subdirectories, which are created as needed.
+                    Without it, new names that contain a path separator are refused.
+                    Either way, items can't be moved out of the folder.",
+                )
+                .action(ArgAction::SetTrue)
+                .long("create-dirs")
+                .required(false),
+        )
+        .arg(
+            Arg::new("case")
+                .help("Converts names to a style of case, instead of numbering them.")
+                .long_help(
+                    "Converts the stem of names to a style of case, instead of numbering them:
+                    `lower`, `upper`, `title` (capitalising every word, except small words such as
+                    `of` in the middle), `sentence` (capitalising the first word only), `snake`
+                    (snake_case), `kebab` (kebab-case), `camel` (camelCase) or `pascal` (PascalCase).",
+                )
+                .long("case")
+                .required(false)
+                .value_parser(PossibleValuesParser::new([
+                    "lower", "upper", "title", "sentence", "snake", "kebab", "camel", "pascal",
+                ]))
+                .value_hint(ValueHint::Other)
+                .value_name("STYLE")
+                .action(ArgAction::Set)
+                .conflicts_with_all([
+                    "template",
+                    "match-rename",
+                    "rule",
+                    "prefix",
+                    "padding",
+                    "padding-direction",
+                    "origin",
+                ])
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("transliterate")
+                .help("Converts names to ASCII, replacing characters that can't be with `_` unless given something else.")
+                .long_help(
+                    "Converts names to ASCII, instead of numbering them, as in `Café Ünïcode.txt` to
+                    `Cafe Unicode.txt` or `北京.txt` to `Bei Jing.txt`. Characters that have no
+                    ASCII equivalent are replaced with `_`, unless given something else.",
+                )
+                .long("transliterate")
+                .required(false)
+                .action(ArgAction::Set)
+                .num_args(0..=1)
+                .default_missing_value("_")
+                .value_hint(ValueHint::Other)
+                .value_name("FALLBACK")
+                .conflicts_with_all([
+                    "template",
+                    "match-rename",
+                    "rule",
+                    "prefix",
+                    "padding",
+                    "padding-direction",
+                    "origin",
+                ])
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("slug")
+                .help("Converts names to lower-case ASCII, with `-` or the given separator between words.")
+                .long_help(
+                    "Converts the stem of names to lower-case ASCII, instead of numbering them, with
+                    every run of characters that aren't letters or digits replaced by `-`, unless
+                    given another separator. `Café Ünïcode (2023).txt` becomes
+                    `cafe-unicode-2023.txt`.",
+                )
+                .long("slug")
+                .required(false)
+                .action(ArgAction::Set)
+                .num_args(0..=1)
+                .default_missing_value("-")
+                .value_hint(ValueHint::Other)
+                .value_name("SEPARATOR")
+                .conflicts_with_all([
+                    "template",
+                    "match-rename",
+                    "rule",
+                    "case",
+                    "prefix",
+                    "padding",
+                    "padding-direction",
+                    "origin",
+                ])
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("normalize")
+                .help("Normalises names to the given Unicode form, before matching and after renaming.")
+                .long_help(
+                    "Normalises names to the given Unicode form. Names are normalised before
+                    `--match` and `--match-rename` see them, so `café` matches however it's
+                    written, and new names are converted to the form, including those built by
+                    `--template`. Without a template or `--match-rename`, items are only
+                    normalised instead of being numbered. Names that would be the same as an
+                    existing item once both are normalised are reported.",
+                )
+                .long("normalize")
+                .required(false)
+                .value_parser(PossibleValuesParser::new(["nfc", "nfd", "nfkc", "nfkd"]))
+                .value_hint(ValueHint::Other)
+                .value_name("FORM")
+                .action(ArgAction::Set)
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("case-extension")
+                .help("Converts the case of extensions as well.")
+                .action(ArgAction::SetTrue)
+                .long("case-extension")
+                .required(false)
+                .requires("case"),
+        )
         .arg(
             Arg::new("match")
                 .help("Valid RegEx for matching input files (see 'match-rename' argument).")
@@ -155,6 +426,47 @@ fn build_app() -> Command {
                 .required(false)
                 .use_value_delimiter(false)
         )
+        .arg(
+            Arg::new("ignore-case")
+                .help("Makes 'match' ignore case.")
+                .action(ArgAction::SetTrue)
+                .short('i')
+                .long("ignore-case")
+                .requires("match")
+                .required(false),
+        )
+        .arg(
+            Arg::new("multi-line")
+                .help("Makes `^` and `$` in 'match' match at the start and end of lines.")
+                .action(ArgAction::SetTrue)
+                .long("multi-line")
+                .requires("match")
+                .required(false),
+        )
+        .arg(
+            Arg::new("dot-all")
+                .help("Makes `.` in 'match' match new lines as well.")
+                .action(ArgAction::SetTrue)
+                .long("dot-all")
+                .requires("match")
+                .required(false),
+        )
+        .arg(
+            Arg::new("ignore-whitespace")
+                .help("Ignores whitespace in 'match', and allows `#` comments.")
+                .action(ArgAction::SetTrue)
+                .long("ignore-whitespace")
+                .requires("match")
+                .required(false),
+        )
+        .arg(
+            Arg::new("ascii")
+                .help("Makes classes such as `\\w` and `\\d` in 'match' only match ASCII.")
+                .action(ArgAction::SetTrue)
+                .long("ascii")
+                .requires("match")
+                .required(false),
+        )
         .arg(
             Arg::new("match-rename")
                 .help("Use capture groups from 'match' argument to rename files. See `--help` for more info.")
@@ -162,9 +474,22 @@ fn build_app() -> Command {
                     "Use capture groups from 'match' argument to rename files.
                     Capture group numbers need a `$` prefix, so `$1` for the first, 
                     `$2` for the second, and so on, with `$0` matching the entire name.
+                    Named groups, as in `(?P<season>\\d+)`, are used as `$season`.
+                    Every group that is used has to exist in 'match'.
                     Recommend using `--dry-run` flag.
                     If it fails to see groups try using `${1}`, as in surround the
-                    group index with `{}`.",
+                    group index with `{}`.
+                    Inside braces, filters can follow a `|`, as in `${1|upper}` or
+                    `${2|pad:3}`. The filters are `lower`, `upper`, `title`, `trim`,
+                    `ascii[:FALLBACK]`, `slug[:SEPARATOR]`, `pad:WIDTH[:FILL]`,
+                    `truncate:LENGTH` and `replace:FROM:TO`.
+                    Numeric groups can be changed with `+`, `-`, `*`, `/` and `%`,
+                    applied from left to right, and padded after a `:`, so
+                    `${2+12:02}` adds 12 to the second group and pads it to 2 digits.
+                    `$n` is a counter, which starts at `--origin` and is padded like
+                    numbered names unless it has a format of its own. `${n@1}` counts
+                    separately for every value of the first group. Because of this,
+                    'match' can't have a group called `n` when the counter is used.",
                 )
                 .action(ArgAction::Set)
                 .value_parser(ValueParser::new(NonEmptyStringValueParser::new()))
@@ -174,13 +499,185 @@ fn build_app() -> Command {
                 .required(false)
                 .use_value_delimiter(false)
         )
+        .arg(
+            Arg::new("all")
+                .help("Replaces every match of 'match' instead of only the first.")
+                .action(ArgAction::SetTrue)
+                .short('g')
+                .long("all")
+                .requires("match-rename")
+                .required(false),
+        )
+        .arg(
+            Arg::new("rule")
+                .help("A substitution rule such as `s/regex/replacement/flags`, applied to every name. Can be repeated. See `--help` for more info.")
+                .long_help(
+                    "A substitution rule such as `s/regex/replacement/flags`, applied to
+                    every name after `--match-rename`. Can be given several times, and
+                    the rules are applied in order. The replacement uses the same syntax
+                    as `--match-rename`, and any character can take the place of `/`.
+                    The flags are `g` (replace every match), `i` (ignore case),
+                    `m` (multi-line), `s` (`.` matches new lines), `x` (verbose regex)
+                    and `e` (leave the extension alone).",
+                )
+                .short('s')
+                .long("rule")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(ValueParser::new(NonEmptyStringValueParser::new()))
+                .value_hint(ValueHint::Other)
+                .value_name("RULE")
+                .use_value_delimiter(false),
+        )
         .arg(
             Arg::new("dry-run")
                 .help("Disables performing actual renaming.")
                 .action(ArgAction::SetTrue)
-                .value_hint(ValueHint::Other)
-                .value_parser(ValueParser::bool())
                 .long("dry-run")
                 .required(false),
         )
+        .arg(
+            Arg::new("sort")
+                .help("Order in which items are numbered. Defaults to `natural`")
+                .long("sort")
+                .required(false)
+                .value_parser(PossibleValuesParser::new([
+                    "natural",
+                    "name",
+                    "mtime",
+                    "ctime",
+                    "size",
+                    "extension",
+                    "none",
+                ]))
+                .value_hint(ValueHint::Other)
+                .value_name("ORDER")
+                .action(ArgAction::Set)
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("reverse")
+                .help("Reverses the sort order.")
+                .action(ArgAction::SetTrue)
+                .long("reverse")
+                .required(false),
+        )
+        .arg(
+            Arg::new("recursive")
+                .help("Also renames items in subdirectories.")
+                .action(ArgAction::SetTrue)
+                .short('r')
+                .long("recursive")
+                .required(false),
+        )
+        .arg(
+            Arg::new("min-depth")
+                .help("Only renames items at least this many levels deep, where `1` is the folder's contents.")
+                .long("min-depth")
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(usize))
+                .value_hint(ValueHint::Other)
+                .value_name("DEPTH")
+                .requires("recursive")
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("max-depth")
+                .help("Descends at most this many levels deep, where `1` is the folder's contents.")
+                .long("max-depth")
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(usize))
+                .value_hint(ValueHint::Other)
+                .value_name("DEPTH")
+                .requires("recursive")
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("counter")
+                .help("Whether numbering continues across directories or restarts in each. Defaults to `global`")
+                .long("counter")
+                .required(false)
+                .value_parser(PossibleValuesParser::new(["global", "directory"]))
+                .value_hint(ValueHint::Other)
+                .value_name("SCOPE")
+                .action(ArgAction::Set)
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("on-conflict")
+                .help("What to do when an item's new name is already taken. Defaults to `fail-all`")
+                .long("on-conflict")
+                .required(false)
+                .value_parser(PossibleValuesParser::new([
+                    "fail-all",
+                    "fail",
+                    "skip",
+                    "overwrite",
+                    "auto-suffix",
+                    "suffix",
+                    "keep-newer",
+                    "keep-larger",
+                    "prompt",
+                    "interactive",
+                ]))
+                .value_hint(ValueHint::Other)
+                .value_name("POLICY")
+                .action(ArgAction::Set)
+                .use_value_delimiter(false),
+        )
+        .arg(
+            Arg::new("transactional")
+                .help("Undoes every completed rename if any rename fails.")
+                .action(ArgAction::SetTrue)
+                .long("transactional")
+                .required(false),
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::build_app;
+    use rena::Arguments;
+
+    #[test]
+    fn verify_app() {
+        build_app().debug_assert();
+    }
+
+    #[test]
+    fn normalize_with_numbering() {
+        let parse = |args: &[&str]| {
+            let matches = build_app()
+                .try_get_matches_from(["rena", "."].iter().chain(args))
+                .expect("failed to parse arguments");
+
+            Arguments::try_from(matches)
+        };
+
+        assert!(parse(&[
+            "-m",
+            "ep (\\w)",
+            "--match-rename",
+            "E${n}",
+            "--origin",
+            "1",
+            "--padding",
+            "2",
+            "--normalize",
+            "nfc",
+        ])
+        .is_ok());
+        assert!(parse(&["--origin", "1", "--normalize", "nfc"]).is_err());
+        assert!(parse(&[
+            "--template",
+            "{n}{ext}",
+            "--origin",
+            "1",
+            "--normalize",
+            "nfc"
+        ])
+        .is_ok());
+    }
 }
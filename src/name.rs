@@ -0,0 +1,72 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Names as the bytes they are made of, so that names which aren't valid
+//! UTF-8 survive being renamed.
+
+use color_eyre::{eyre::eyre, Result};
+use std::ffi::{OsStr, OsString};
+
+/// The bytes of `name`, exactly as they are.
+pub fn to_bytes(name: &OsStr) -> &[u8] {
+    name.as_encoded_bytes()
+}
+
+/// Turns bytes back into a name.
+///
+/// # Errors
+///
+/// Returns an error if the platform can't store `bytes` as a name, which is
+/// only possible outside of Unix.
+#[cfg_attr(unix, allow(clippy::unnecessary_wraps))]
+pub fn from_bytes(bytes: Vec<u8>) -> Result<OsString> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+
+        Ok(OsString::from_vec(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        String::from_utf8(bytes).map(OsString::from).map_err(|e| {
+            eyre!(
+                "`{}` isn't a valid name.",
+                String::from_utf8_lossy(e.as_bytes())
+            )
+        })
+    }
+}
+
+/// The text of `value`, for anything that has to treat it as text.
+///
+/// # Errors
+///
+/// Returns an error naming `what` if `value` isn't valid UTF-8, since it
+/// can't be changed without losing the bytes that aren't.
+pub fn decode<'a>(value: &'a [u8], what: &str) -> Result<&'a str> {
+    std::str::from_utf8(value).map_err(|_| {
+        eyre!(
+            "{what} is `{}`, which isn't valid UTF-8, so it can only be used as it is.",
+            String::from_utf8_lossy(value)
+        )
+    })
+}
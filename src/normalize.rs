@@ -0,0 +1,83 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Unicode normalisation of names.
+
+use unicode_normalization::UnicodeNormalization;
+
+/// A Unicode normalisation form that names can be converted to with
+/// `--normalize`.
+///
+/// The same text can be made of different characters, such as `é` as a
+/// single character or as `e` followed by a combining accent. macOS usually
+/// stores names in the second way (NFD), and most other systems in the first
+/// (NFC).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Normalization {
+    /// Canonical composition, as used by most systems
+    #[default]
+    Nfc,
+    /// Canonical decomposition, as used by macOS
+    Nfd,
+    /// Compatibility composition, which also turns characters such as `ﬁ`
+    /// and `①` into `fi` and `1`
+    Nfkc,
+    /// Compatibility decomposition
+    Nfkd,
+}
+
+impl From<&String> for Normalization {
+    fn from(a: &String) -> Self {
+        let a = a.to_lowercase();
+
+        match a.as_ref() {
+            "nfc" => Self::Nfc,
+            "nfd" => Self::Nfd,
+            "nfkc" => Self::Nfkc,
+            "nfkd" => Self::Nfkd,
+            _ => unreachable!(
+                "If this is reached, something in validation has gone *horribly* wrong."
+            ),
+        }
+    }
+}
+
+impl Normalization {
+    /// Converts `text` to the normalisation form.
+    #[must_use]
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Self::Nfc => text.nfc().collect(),
+            Self::Nfd => text.nfd().collect(),
+            Self::Nfkc => text.nfkc().collect(),
+            Self::Nfkd => text.nfkd().collect(),
+        }
+    }
+
+    /// Converts `name` to the normalisation form, if it's valid UTF-8.
+    #[must_use]
+    pub fn apply_bytes(self, name: &[u8]) -> Option<Vec<u8>> {
+        std::str::from_utf8(name)
+            .ok()
+            .map(|x| self.apply(x).into_bytes())
+    }
+}
@@ -0,0 +1,760 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Computing what a run is going to do, without touching the disk.
+
+use crate::{
+    atomic::is_case_change,
+    conflict::{self, Resolution},
+    filter, filter_items, filter_items_regex,
+    limit::Limit,
+    name,
+    normalize::Normalization,
+    replace::{Counters, Replacement},
+    sanitize::{self, Sanitized},
+    sort,
+    template::Padding,
+    Arguments, CounterScope, PaddingDirection, Template,
+};
+use color_eyre::{eyre::eyre, Result};
+use paris::warn;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fmt,
+    fs::ReadDir,
+    path::{self, Component, Path, PathBuf},
+};
+
+/// A single planned rename operation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameItem {
+    /// Where the item currently is
+    pub original_path: PathBuf,
+    /// Where the item will be moved to
+    pub new_path: PathBuf,
+    /// Whether to replace a file that already exists at `new_path`
+    pub overwrite: bool,
+}
+
+/// A problem found while validating a plan, which keeps it from being executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The target of the rename already exists on disk, and isn't renamed
+    /// away by the plan.
+    TargetExists(RenameItem),
+    /// Several items would be renamed to the same target.
+    DuplicateTarget {
+        /// The shared target
+        target: PathBuf,
+        /// Every item that would be renamed to it, in plan order
+        sources: Vec<PathBuf>,
+    },
+    /// The new name is empty, `.` or `..`.
+    InvalidName(RenameItem),
+    /// The new name contains a path separator, which would move the item to
+    /// another directory without [`RenamePlan::create_dirs`].
+    OtherDirectory(RenameItem),
+    /// The new name would move the item out of the folder.
+    OutsideFolder(RenameItem),
+    /// The new name is different from that of an item that already exists,
+    /// but the same once both are normalised.
+    EquivalentExists {
+        /// The item that would be renamed
+        item: RenameItem,
+        /// The item that already exists
+        existing: PathBuf,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TargetExists(item) => write!(
+                f,
+                "Item `{}` already exists, unable to rename `{}`.",
+                item.new_path.to_string_lossy(),
+                item.original_path.to_string_lossy()
+            ),
+            Self::DuplicateTarget { target, sources } => write!(
+                f,
+                "{} items would be renamed to `{}`: {}",
+                sources.len(),
+                target.to_string_lossy(),
+                sources
+                    .iter()
+                    .map(|x| format!("`{}`", x.to_string_lossy()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::InvalidName(item) => write!(
+                f,
+                "Item `{}` would be renamed to `{}`, which isn't a valid name.",
+                item.original_path.to_string_lossy(),
+                item.new_path.to_string_lossy()
+            ),
+            Self::OtherDirectory(item) => write!(
+                f,
+                "Item `{}` would be moved to `{}`, in another directory (use `--create-dirs` to allow this).",
+                item.original_path.to_string_lossy(),
+                item.new_path.to_string_lossy()
+            ),
+            Self::OutsideFolder(item) => write!(
+                f,
+                "Item `{}` would be moved to `{}`, outside of the folder.",
+                item.original_path.to_string_lossy(),
+                item.new_path.to_string_lossy()
+            ),
+            Self::EquivalentExists { item, existing } => write!(
+                f,
+                "Item `{}` would be renamed to `{}`, which is the same as `{}` once normalised.",
+                item.original_path.to_string_lossy(),
+                item.new_path.to_string_lossy(),
+                existing.to_string_lossy()
+            ),
+        }
+    }
+}
+
+/// Everything a run is going to do, as computed by [`plan()`].
+///
+/// Items can be inspected, filtered or reordered before the plan is handed
+/// over to [`execute()`](crate::execute()), which refuses to start while
+/// [`RenamePlan::validate()`] reports any problems.
+#[derive(Debug, Clone, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RenamePlan {
+    /// Folder the plan acts in
+    pub folder: PathBuf,
+    /// The renames that will be performed, in order
+    pub items: Vec<RenameItem>,
+    /// Problems found when the plan was computed
+    pub problems: Vec<Problem>,
+    /// Conflicts that were resolved when the plan was computed, and how
+    pub resolutions: Vec<Resolution>,
+    /// Items that already have their new name, and were left out
+    pub unchanged: Vec<PathBuf>,
+    /// New names that were changed to be valid on another platform
+    pub sanitized: Vec<Sanitized>,
+    /// Whether to only log the renames instead of performing them
+    pub dry_run: bool,
+    /// Whether to log every completed rename
+    pub verbose: bool,
+    /// Whether to undo every completed rename if any of them fails
+    pub transactional: bool,
+    /// Directory to write an undo journal to, if any
+    pub journal: Option<PathBuf>,
+    /// Whether items may be moved to other directories inside the folder,
+    /// which are created as needed
+    pub create_dirs: bool,
+    /// Unicode normalisation form new names were converted to, if any, which
+    /// existing names are compared in as well
+    pub normalize: Option<Normalization>,
+}
+
+impl RenamePlan {
+    /// Checks the whole batch for collisions, without touching the disk.
+    ///
+    /// Reports every item whose new name is empty, `.` or `..`, or would move
+    /// it out of the folder (or to another directory, unless
+    /// [`RenamePlan::create_dirs`] is set), every target shared by more than
+    /// one item, and every target that already exists on disk without being
+    /// renamed away by the plan itself or marked to be overwritten. Targets
+    /// that only exist because they differ from the item's own name in case,
+    /// on a filesystem that ignores case, are fine. With
+    /// [`RenamePlan::normalize`], targets that are only the same as an item
+    /// on disk once both are normalised are reported as well. An empty result
+    /// means the plan can be executed.
+    #[must_use]
+    pub fn validate(&self) -> Vec<Problem> {
+        let invalid = self.items.iter().filter_map(|x| self.check_target(x));
+        let mut order = Vec::new();
+        let mut targets: HashMap<&PathBuf, Vec<PathBuf>> = HashMap::new();
+
+        // Invalid names are only reported once, not also as collisions.
+        let valid = self
+            .items
+            .iter()
+            .filter(|x| self.check_target(x).is_none())
+            .collect::<Vec<_>>();
+
+        for x in &valid {
+            targets
+                .entry(&x.new_path)
+                .or_insert_with(|| {
+                    order.push(&x.new_path);
+                    Vec::new()
+                })
+                .push(x.original_path.clone());
+        }
+
+        let duplicates = order.into_iter().filter_map(|target| {
+            let sources = targets.remove(target)?;
+
+            (sources.len() > 1).then(|| Problem::DuplicateTarget {
+                target: target.clone(),
+                sources,
+            })
+        });
+
+        // Targets that are vacated by the same batch are fine, the executor
+        // orders the renames around them.
+        let sources = self
+            .items
+            .iter()
+            .map(|x| &x.original_path)
+            .collect::<HashSet<_>>();
+        let existing = valid
+            .iter()
+            .filter(|x| !x.overwrite && x.new_path.exists() && !sources.contains(&x.new_path))
+            .filter(|x| !is_case_change(&x.original_path, &x.new_path))
+            .map(|&x| Problem::TargetExists(x.clone()));
+        let equivalent = self
+            .normalize
+            .map_or_else(Vec::new, |form| equivalent_existing(&valid, &sources, form));
+
+        invalid
+            .chain(duplicates)
+            .chain(existing)
+            .chain(equivalent)
+            .collect()
+    }
+
+    /// Checks that the new name of `item` is valid, and stays where it has to.
+    fn check_target(&self, item: &RenameItem) -> Option<Problem> {
+        let absolute = |x: &Path| normalize(&path::absolute(x).unwrap_or_else(|_| x.to_path_buf()));
+        let folder = resolve(&absolute(&self.folder));
+        let target = absolute(&item.new_path);
+        // A directory on the way may be a symlink that leads out of the folder.
+        let target = match (target.parent(), target.file_name()) {
+            (Some(parent), Some(name)) => resolve(parent).join(name),
+            _ => target,
+        };
+
+        if !is_valid_name(&item.new_path) {
+            Some(Problem::InvalidName(item.clone()))
+        } else if target == folder || !target.starts_with(&folder) {
+            Some(Problem::OutsideFolder(item.clone()))
+        } else if !self.create_dirs && item.new_path.parent() != item.original_path.parent() {
+            Some(Problem::OtherDirectory(item.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Finds items whose new name is the same as that of an item on disk once
+/// both are normalised, without being the same name.
+///
+/// Items that are renamed away by the plan don't count, and neither does the
+/// item itself, which may only be normalised.
+fn equivalent_existing(
+    items: &[&RenameItem],
+    sources: &HashSet<&PathBuf>,
+    form: Normalization,
+) -> Vec<Problem> {
+    let mut listings: HashMap<&Path, HashMap<Vec<u8>, Vec<PathBuf>>> = HashMap::new();
+    let mut problems = Vec::new();
+
+    for &item in items {
+        let (Some(dir), Some(name)) = (item.new_path.parent(), item.new_path.file_name()) else {
+            continue;
+        };
+        let Some(name) = form.apply_bytes(name::to_bytes(name)) else {
+            continue;
+        };
+        let listing = listings.entry(dir).or_insert_with(|| {
+            let mut listing = HashMap::<_, Vec<_>>::new();
+
+            for x in dir.read_dir().into_iter().flatten().flatten() {
+                if let Some(normalized) = form.apply_bytes(name::to_bytes(&x.file_name())) {
+                    listing.entry(normalized).or_default().push(x.path());
+                }
+            }
+
+            listing
+        });
+        let existing = listing
+            .get(&name)
+            .into_iter()
+            .flatten()
+            .find(|&x| *x != item.new_path && *x != item.original_path && !sources.contains(x));
+
+        if let Some(existing) = existing {
+            problems.push(Problem::EquivalentExists {
+                item: item.clone(),
+                existing: existing.clone(),
+            });
+        }
+    }
+
+    problems
+}
+
+/// Whether the last component of `path`, as it was written, is a name that
+/// can be given to an item.
+fn is_valid_name(path: &Path) -> bool {
+    let path = path.as_os_str().to_string_lossy();
+    let name = path
+        .rsplit(std::path::is_separator)
+        .next()
+        .unwrap_or_default();
+
+    !matches!(name, "" | "." | "..")
+}
+
+/// Resolves `.` and `..` in `path`, without touching the disk.
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for x in path.components() {
+        match x {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(result.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                result.pop();
+            }
+            x => result.push(x),
+        }
+    }
+
+    result
+}
+
+/// Resolves the symlinks in the part of `path` that exists, leaving the rest
+/// of it as it is.
+fn resolve(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return rest
+                .into_iter()
+                .rev()
+                .fold(resolved, |x, name| x.join(name));
+        }
+
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// Where `item` ends up when given `name`, which may contain separators if
+/// `--create-dirs` is used.
+fn target(item: &Path, name: &OsStr, args: &Arguments) -> PathBuf {
+    let target = item.with_file_name(name);
+
+    // Invalid names are kept as they are, so they can be reported.
+    if args.create_dirs && is_valid_name(&target) {
+        normalize(&target)
+    } else {
+        target
+    }
+}
+
+/// Computes the renames that would be performed for the given arguments.
+///
+/// Nothing on disk is modified. Items that already have their new name are
+/// left out, and recorded in [`RenamePlan::unchanged`]. New names that had
+/// to be changed by [`Arguments::sanitize`] are recorded in
+/// [`RenamePlan::sanitized`]. Collisions are first
+/// resolved according to [`Arguments::on_conflict`], and those that remain
+/// are recorded in [`RenamePlan::problems`].
+///
+/// # Errors
+///
+/// Returns an error in the following circumstances:
+///
+/// - The target doesn't exist
+/// - The target is not a directory
+/// - We can't read the directory's contents
+/// - A new name can't be formatted
+/// - The `--match-rename` pattern is invalid, refers to a group that doesn't
+///   exist, or is given without a `--match` pattern
+/// - A conflict can't be resolved
+pub fn plan(args: &Arguments) -> Result<RenamePlan> {
+    if !args.folder.exists() {
+        return Err(eyre!(format!(
+            "Folder `{}` does not exist.",
+            args.folder.to_string_lossy()
+        )));
+    }
+
+    if !args.folder.is_dir() {
+        return Err(eyre!(format!(
+            "`{}` is not a folder.",
+            args.folder.to_string_lossy()
+        )));
+    }
+
+    let read = match args.folder.read_dir() {
+        Ok(read) => read,
+        Err(e) => {
+            return Err(eyre!(format!(
+                "Unable to read directory {}: {}",
+                args.folder.to_string_lossy(),
+                e
+            )));
+        }
+    };
+
+    let mut groups = Vec::new();
+
+    gather(&args.folder, read, 1, args, &mut groups);
+
+    let mut items = if args.match_rename.is_some() || !args.rules.is_empty() {
+        plan_regex(&groups, args)?
+    } else if args.case.is_some()
+        || args.transliterate.is_some()
+        || args.slug.is_some()
+        || (args.normalize.is_some() && args.template.is_none())
+    {
+        plan_convert(&groups, args)
+    } else {
+        plan_normal(&groups, args)?
+    };
+    let mut sanitized = Vec::new();
+
+    if let Some(form) = args.normalize {
+        for x in items.iter_mut().filter(|x| is_valid_name(&x.new_path)) {
+            let name = x.new_path.file_name().map(name::to_bytes);
+
+            if let Some(name) = name.and_then(|x| form.apply_bytes(x)) {
+                x.new_path.set_file_name(name::from_bytes(name)?);
+            }
+        }
+    }
+
+    if let Some(sanitizer) = &args.sanitize {
+        let planned = items.iter().map(|x| x.new_path.clone()).collect::<Vec<_>>();
+
+        for x in items.iter_mut().filter(|x| is_valid_name(&x.new_path)) {
+            let Some(name) = x.new_path.file_name() else {
+                continue;
+            };
+
+            x.new_path = x.new_path.with_file_name(sanitizer.replace(name));
+        }
+
+        // Names cut to the length every platform accepts are kept apart.
+        shorten(&mut items, &sanitize::MAX_LENGTH);
+
+        sanitized = planned
+            .iter()
+            .zip(&items)
+            .filter(|&(planned, x)| *planned != x.new_path)
+            .map(|(planned, x)| Sanitized {
+                planned: planned.clone(),
+                new_path: x.new_path.clone(),
+            })
+            .collect();
+    }
+
+    if let Some(limit) = &args.max_length {
+        shorten(&mut items, limit);
+    }
+    let (unchanged, items) = items
+        .into_iter()
+        .partition::<Vec<_>, _>(|x| x.new_path == x.original_path);
+    let (items, resolutions) = conflict::resolve(items, args.on_conflict)?;
+
+    let mut plan = RenamePlan {
+        folder: args.folder.clone(),
+        items,
+        problems: Vec::new(),
+        resolutions,
+        unchanged: unchanged.into_iter().map(|x| x.original_path).collect(),
+        sanitized,
+        dry_run: args.dry_run,
+        verbose: args.verbose,
+        transactional: args.transactional,
+        journal: args.journal.clone(),
+        create_dirs: args.create_dirs,
+        normalize: args.normalize,
+    };
+
+    plan.problems = plan.validate();
+
+    Ok(plan)
+}
+
+/// Collects the items in `dir` (which is `depth` levels below the folder),
+/// and then those in its subdirectories if the run is recursive.
+///
+/// Every directory gets its own group, sorted on its own. Directories are
+/// visited in the same order, parents before their children.
+fn gather(
+    dir: &Path,
+    read: ReadDir,
+    depth: usize,
+    args: &Arguments,
+    groups: &mut Vec<Vec<PathBuf>>,
+) {
+    if depth >= args.min_depth {
+        let mut items = match &args.match_regex {
+            Some(r) => filter_items_regex(read, args.directory, r, args.normalize),
+            None => filter_items(read, args.directory),
+        };
+
+        sort::sort(&mut items, args.sort, args.reverse);
+        groups.push(items);
+    }
+
+    if !args.recursive || args.max_depth.is_some_and(|max| depth >= max) {
+        return;
+    }
+
+    // Listed separately, since the filter above may have left them out.
+    let mut subdirs = match dir.read_dir() {
+        Ok(read) => filter_items(read, true),
+        Err(e) => {
+            warn!("Unable to read directory {}: {}", dir.to_string_lossy(), e);
+            return;
+        }
+    };
+
+    sort::sort(&mut subdirs, args.sort, args.reverse);
+
+    for x in subdirs {
+        match x.read_dir() {
+            Ok(read) => gather(&x, read, depth + 1, args, groups),
+            Err(e) => warn!("Unable to read directory {}: {}", x.to_string_lossy(), e),
+        }
+    }
+}
+
+/// Cuts every new name that is too long short, keeping them apart from each
+/// other.
+///
+/// Names that only become the same when they are cut get a `~1`, `~2`, ...
+/// tag. Names that were the same to begin with are left for conflict
+/// resolution.
+fn shorten(items: &mut [RenameItem], limit: &Limit) {
+    // Which full name every new name stands for.
+    let mut taken = items
+        .iter()
+        .map(|x| (x.new_path.clone(), x.new_path.clone()))
+        .collect::<HashMap<_, _>>();
+
+    for x in items.iter_mut().filter(|x| is_valid_name(&x.new_path)) {
+        let Some(name) = x.new_path.file_name().map(OsStr::to_os_string) else {
+            continue;
+        };
+        let Some(short) = limit.truncate(&name, "") else {
+            continue;
+        };
+        let mut new_path = x.new_path.with_file_name(short);
+        let mut n = 1;
+
+        while taken.get(&new_path).is_some_and(|full| *full != x.new_path) {
+            let Some(short) = limit.truncate(&name, &format!("~{n}")) else {
+                break;
+            };
+
+            new_path = x.new_path.with_file_name(short);
+            n += 1;
+        }
+
+        taken.insert(new_path.clone(), x.new_path.clone());
+        x.new_path = new_path;
+    }
+}
+
+fn plan_normal(groups: &[Vec<PathBuf>], args: &Arguments) -> Result<Vec<RenameItem>> {
+    let default;
+    let template = if let Some(template) = &args.template {
+        template
+    } else {
+        let align = alignment(args);
+
+        default = Template::parse(&format!("{{prefix}}_{{n:0{align}{}}}{{ext}}", args.padding))?;
+        &default
+    };
+
+    let mut numbered = Vec::new();
+    let mut counter = args.origin..;
+
+    for items in groups {
+        if args.counter == CounterScope::Directory {
+            counter = args.origin..;
+        }
+
+        numbered.extend(items.iter().zip(&mut counter));
+    }
+
+    numbered
+        .into_iter()
+        .map(|(x, count)| {
+            let name = template.render(x, count, &args.prefix)?;
+
+            Ok(RenameItem {
+                original_path: x.clone(),
+                new_path: target(x, &name, args),
+                ..RenameItem::default()
+            })
+        })
+        .collect()
+}
+
+fn plan_regex(groups: &[Vec<PathBuf>], args: &Arguments) -> Result<Vec<RenameItem>> {
+    let mut rules = Vec::new();
+
+    if let Some(match_rename) = &args.match_rename {
+        let regex = args
+            .match_regex
+            .as_ref()
+            .ok_or_else(|| eyre!("`--match-rename` needs a `--match` pattern."))?;
+
+        rules.push((
+            regex,
+            Replacement::parse(match_rename)?,
+            args.replace_all,
+            false,
+        ));
+    }
+
+    for x in &args.rules {
+        rules.push((
+            &x.regex,
+            Replacement::parse(&x.replacement)?,
+            x.all,
+            x.keep_extension,
+        ));
+    }
+
+    for (regex, replacement, ..) in &rules {
+        replacement.validate(regex)?;
+    }
+
+    let padding = format!("0{}{}", alignment(args), args.padding);
+    let padding =
+        Padding::parse(&padding).ok_or_else(|| eyre!("Invalid padding of {}.", args.padding))?;
+    let mut counters = Counters::new(args.origin, padding);
+    let mut result = Vec::new();
+
+    for items in groups {
+        if args.counter == CounterScope::Directory {
+            counters.reset();
+        }
+
+        for x in items {
+            let mut name = name::to_bytes(x.file_name().expect("there to be a filename")).to_vec();
+
+            // Rules see the name as `--match` did.
+            if let Some(normalized) = args.normalize.and_then(|form| form.apply_bytes(&name)) {
+                name = normalized;
+            }
+
+            for (regex, replacement, all, keep_extension) in &rules {
+                let extension = keep_extension
+                    .then(|| name.iter().rposition(|&x| x == b'.').filter(|&i| i > 0))
+                    .flatten()
+                    .unwrap_or(name.len());
+                let (stem, extension) = name.split_at(extension);
+
+                name = [
+                    replacement.replace(regex, stem, *all, &mut counters)?,
+                    extension.to_vec(),
+                ]
+                .concat();
+            }
+
+            counters.advance();
+
+            result.push(RenameItem {
+                original_path: x.clone(),
+                new_path: target(x, &name::from_bytes(name)?, args),
+                ..RenameItem::default()
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// Converts names to ASCII, then to a case or a slug, as asked for.
+fn convert(text: &str, args: &Arguments) -> String {
+    let mut text = args.transliterate.as_ref().map_or_else(
+        || text.to_owned(),
+        |fallback| filter::transliterate(text, fallback),
+    );
+
+    if let Some(case) = args.case {
+        text = case.apply(&text);
+    }
+    if let Some(separator) = &args.slug {
+        text = filter::slug(&text, separator);
+    }
+
+    text
+}
+
+fn plan_convert(groups: &[Vec<PathBuf>], args: &Arguments) -> Vec<RenameItem> {
+    let mut result = Vec::new();
+
+    for x in groups.iter().flatten() {
+        let name = x.file_name().expect("there to be a filename");
+        let what = format!("The name of `{}`", x.to_string_lossy());
+        let name = match name::decode(name::to_bytes(name), &what) {
+            Ok(name) => name,
+            Err(e) => {
+                warn!("{} It's left out.", e);
+                continue;
+            }
+        };
+        let (stem, extension) =
+            name.split_at(name.rfind('.').filter(|&i| i > 0).unwrap_or(name.len()));
+        let extension = if extension.is_empty() {
+            String::new()
+        } else if args.case_extension || args.slug.is_some() {
+            format!(".{}", convert(&extension[1..], args))
+        } else if let Some(fallback) = &args.transliterate {
+            filter::transliterate(extension, fallback)
+        } else {
+            extension.to_owned()
+        };
+
+        result.push(RenameItem {
+            original_path: x.clone(),
+            new_path: x.with_file_name(convert(stem, args) + &extension),
+            ..RenameItem::default()
+        });
+    }
+
+    result
+}
+
+/// The alignment of numbers given with `--padding-direction`.
+const fn alignment(args: &Arguments) -> char {
+    match args.padding_direction {
+        PaddingDirection::Left => '>',
+        PaddingDirection::Right => '<',
+        PaddingDirection::Middle => '^',
+    }
+}
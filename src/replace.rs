@@ -0,0 +1,592 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Replacement patterns for renaming with capture groups.
+
+use crate::{
+    filter::{self, Filter},
+    name,
+    template::Padding,
+};
+use color_eyre::{eyre::eyre, Result};
+use regex::bytes::{Captures, Regex, RegexBuilder};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// A parsed `--match-rename` pattern.
+///
+/// This follows the syntax of [`Regex::replace()`]: `$1` or `${1}` is the
+/// first capture group, `$name` or `${name}` the group called `name`, and
+/// `$$` a literal `$`. `$n` or `${n}` is a counter instead, which counts up
+/// for every item that is renamed, and `${n@1}` or `${n@name}` a counter that
+/// counts separately for every value of a group.
+///
+/// Inside braces, a group can also be followed by:
+///
+/// - Arithmetic, as in `${2+12}` or `${1*10%7}`: the group is read as a whole
+///   number, and `+`, `-`, `*`, `/` and `%` are applied to it from left to
+///   right
+/// - A width to pad to after a `:`, as in `${2+12:02}`, in the same format as
+///   in [templates](crate::Template)
+/// - [Filters](Filter) after a `|`, as in `${1|pad:2}`
+///
+/// Names are matched as bytes, so groups that aren't valid UTF-8 are kept as
+/// they are, but can't be changed in any of these ways.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replacement {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Group(Expression),
+}
+
+/// A group, and everything that is done to its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Expression {
+    source: String,
+    group: Group,
+    operations: Vec<(Operation, i64)>,
+    padding: Option<Padding>,
+    filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Group {
+    Index(usize),
+    Name(String),
+    Counter(Option<Box<Self>>),
+}
+
+/// The state of every counter in a [`Replacement`], across items.
+#[derive(Debug, Clone)]
+pub struct Counters {
+    origin: usize,
+    padding: Padding,
+    next: HashMap<(String, Vec<u8>), usize>,
+    current: HashMap<(String, Vec<u8>), usize>,
+}
+
+impl Counters {
+    /// Starts counting at `origin`, padding counters that have no format of
+    /// their own with `padding`.
+    pub fn new(origin: usize, padding: Padding) -> Self {
+        Self {
+            origin,
+            padding,
+            next: HashMap::new(),
+            current: HashMap::new(),
+        }
+    }
+
+    /// Starts every counter over.
+    pub fn reset(&mut self) {
+        self.next.clear();
+    }
+
+    /// The number of the current item for the counter `key`.
+    fn get(&mut self, key: (String, Vec<u8>)) -> usize {
+        let next = self.next.get(&key).copied().unwrap_or(self.origin);
+
+        *self.current.entry(key).or_insert(next)
+    }
+
+    /// Moves every counter used by the current item on to the next item.
+    pub fn advance(&mut self) {
+        for (key, number) in self.current.drain() {
+            self.next.insert(key, number + 1);
+        }
+    }
+}
+
+impl Replacement {
+    /// Parses a replacement pattern.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = source;
+
+        while let Some(at) = rest.find('$') {
+            text.push_str(&rest[..at]);
+            rest = &rest[at + 1..];
+
+            if let Some(after) = rest.strip_prefix('$') {
+                text.push('$');
+                rest = after;
+                continue;
+            }
+
+            let (part, after) = if let Some(braced) = rest.strip_prefix('{') {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| eyre!("Unclosed `${{` in replacement `{source}`."))?;
+
+                (parse_group(&braced[..end])?, &braced[end + 1..])
+            } else {
+                let end = name_length(rest);
+
+                if end == 0 {
+                    // Not a group, so the `$` is kept as it is.
+                    text.push('$');
+                    continue;
+                }
+
+                let name = &rest[..end];
+                let digits =
+                    name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+                // `$1_x` is taken as a group called `1_x` by `Regex::replace()`,
+                // which is never what was meant.
+                if digits > 0 && digits < name.len() {
+                    let (index, rest) = name.split_at(digits);
+
+                    return Err(eyre!(
+                        "`${name}` is ambiguous, use `${{{index}}}{rest}` for group {index} followed by `{rest}`."
+                    ));
+                }
+
+                let expression = Expression {
+                    source: format!("${name}"),
+                    group: group(name),
+                    operations: Vec::new(),
+                    padding: None,
+                    filters: Vec::new(),
+                };
+
+                (Part::Group(expression), &rest[end..])
+            };
+
+            if !text.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut text)));
+            }
+            parts.push(part);
+            rest = after;
+        }
+
+        text.push_str(rest);
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Checks that every group the pattern refers to exists in `regex`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing every group that doesn't exist. Groups
+    /// without braces that only exist without their last few characters, as
+    /// in `$season_x` for a group called `season`, are pointed out as well.
+    /// Since `$n` is always the counter, `regex` can't have a group called
+    /// `n` if the pattern uses the counter.
+    pub fn validate(&self, regex: &Regex) -> Result<()> {
+        let counter = self.parts.iter().any(|x| {
+            matches!(x, Part::Group(expression) if matches!(expression.group, Group::Counter(_)))
+        });
+
+        if counter && regex.capture_names().flatten().any(|x| x == "n") {
+            return Err(eyre!(
+                "`{}` has a group called `n`, which can't be used because `$n` is the counter. Give the group another name.",
+                regex.as_str()
+            ));
+        }
+
+        let missing = self
+            .parts
+            .iter()
+            .filter_map(|x| match x {
+                Part::Group(expression) => Some(expression),
+                Part::Text(_) => None,
+            })
+            .filter(|x| !x.group.exists(regex))
+            .map(|x| {
+                let name = x.source.strip_prefix('$').unwrap_or_default();
+                let prefix = (1..name.len())
+                    .rev()
+                    .map(|i| name.split_at(i))
+                    .find(|(prefix, _)| group(prefix).exists(regex));
+
+                match prefix {
+                    Some((prefix, rest)) if !name.starts_with('{') => format!(
+                        "`{}` refers to a group called `{name}`, which doesn't exist (use `${{{prefix}}}{rest}` for group `{prefix}`).",
+                        x.source
+                    ),
+                    _ => format!("`{}` refers to a group that doesn't exist.", x.source),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        Err(eyre!(
+            "Invalid replacement for `{}`, which has {} group(s): {}",
+            regex.as_str(),
+            regex.captures_len() - 1,
+            missing.join(" ")
+        ))
+    }
+
+    /// Replaces the first match of `regex` in `text`, or every match if
+    /// `all` is set. If there is none, `text` is returned as it is.
+    ///
+    /// Counters keep their value until [`Counters::advance()`] is called, so
+    /// every match in an item gets the same number.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if arithmetic is done on a group that isn't a number,
+    /// the arithmetic itself fails, or a group that isn't valid UTF-8 would
+    /// have to be changed.
+    pub fn replace(
+        &self,
+        regex: &Regex,
+        text: &[u8],
+        all: bool,
+        counters: &mut Counters,
+    ) -> Result<Vec<u8>> {
+        let mut result = Vec::new();
+        let mut last = 0;
+
+        for captures in regex
+            .captures_iter(text)
+            .take(if all { usize::MAX } else { 1 })
+        {
+            let whole = captures.get(0).expect("group 0 to always match");
+
+            result.extend_from_slice(&text[last..whole.start()]);
+
+            for part in &self.parts {
+                match part {
+                    Part::Text(text) => result.extend_from_slice(text.as_bytes()),
+                    Part::Group(expression) => {
+                        let value = expression.evaluate(&captures, counters).map_err(|e| {
+                            eyre!(
+                                "Unable to evaluate `{}` for `{}`: {e}",
+                                expression.source,
+                                String::from_utf8_lossy(text)
+                            )
+                        })?;
+
+                        result.extend_from_slice(&value);
+                    }
+                }
+            }
+
+            last = whole.end();
+        }
+
+        result.extend_from_slice(&text[last..]);
+        Ok(result)
+    }
+}
+
+/// A substitution rule, such as `s/\./ /g`.
+///
+/// Rules are written like in `sed`: an `s`, followed by a delimiter, the
+/// regex, the delimiter, the replacement, the delimiter again and finally any
+/// flags. Any character that isn't a letter, digit, `\` or whitespace can be
+/// the delimiter, and it can be escaped with `\` inside the regex or the
+/// replacement. The replacement uses the same syntax as `--match-rename`.
+///
+/// The flags are:
+///
+/// - `g`: replaces every match instead of only the first
+/// - `i`: ignores case
+/// - `m`: makes `^` and `$` match at the start and end of lines
+/// - `s`: makes `.` match new lines
+/// - `x`: ignores whitespace and allows comments in the regex
+/// - `e`: leaves the extension alone, and only works on the rest of the name
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// The regex to find
+    pub regex: Regex,
+    /// What to replace its matches with
+    pub replacement: String,
+    /// Whether to replace every match instead of only the first
+    pub all: bool,
+    /// Whether to leave the extension alone
+    pub keep_extension: bool,
+}
+
+impl Rule {
+    /// Parses a rule such as `s/ +/ /g`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rule isn't shaped like `s/regex/replacement/`,
+    /// has unknown flags, or its regex is invalid.
+    pub fn parse(source: &str) -> Result<Self> {
+        let invalid = || eyre!("Invalid rule `{source}`, expected `s/regex/replacement/flags`.");
+        let mut chars = source.chars();
+
+        if chars.next() != Some('s') {
+            return Err(invalid());
+        }
+
+        let delimiter = chars
+            .next()
+            .filter(|c| !(c.is_alphanumeric() || c.is_whitespace() || *c == '\\'))
+            .ok_or_else(invalid)?;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(c) if c == delimiter => field.push(c),
+                    Some(c) => {
+                        field.push('\\');
+                        field.push(c);
+                    }
+                    None => field.push('\\'),
+                },
+                c if c == delimiter => fields.push(std::mem::take(&mut field)),
+                c => field.push(c),
+            }
+        }
+
+        fields.push(field);
+
+        let [regex, replacement, flags] = <[String; 3]>::try_from(fields).map_err(|_| invalid())?;
+        let mut builder = RegexBuilder::new(&regex);
+        let mut all = false;
+        let mut keep_extension = false;
+
+        for flag in flags.chars() {
+            match flag {
+                'g' => all = true,
+                'i' => _ = builder.case_insensitive(true),
+                'm' => _ = builder.multi_line(true),
+                's' => _ = builder.dot_matches_new_line(true),
+                'x' => _ = builder.ignore_whitespace(true),
+                'e' => keep_extension = true,
+                _ => return Err(eyre!("Unknown flag `{flag}` in rule `{source}`.")),
+            }
+        }
+
+        Ok(Self {
+            regex: builder.build()?,
+            replacement,
+            all,
+            keep_extension,
+        })
+    }
+}
+
+impl FromStr for Rule {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl Expression {
+    fn evaluate(&self, captures: &Captures<'_>, counters: &mut Counters) -> Result<Vec<u8>> {
+        let raw = self.group.value(captures);
+        let changed = matches!(self.group, Group::Counter(_))
+            || !self.operations.is_empty()
+            || self.padding.is_some()
+            || !self.filters.is_empty();
+
+        // Values that are used as they are don't have to be text.
+        if !changed {
+            return Ok(raw.to_vec());
+        }
+
+        let mut value = name::decode(raw, "the capture group")?.to_owned();
+        let counter = match &self.group {
+            Group::Counter(scope) => {
+                let key = scope.as_ref().map_or_else(Default::default, |scope| {
+                    (scope.to_string(), scope.value(captures).to_owned())
+                });
+
+                Some(counters.get(key))
+            }
+            _ => None,
+        };
+
+        if counter.is_some() || !self.operations.is_empty() {
+            let mut number = match counter {
+                Some(counter) => {
+                    i64::try_from(counter).map_err(|_| eyre!("the counter is too large"))?
+                }
+                None => value.trim().parse::<i64>().map_err(|_| {
+                    eyre!("the capture group is `{value}`, which isn't a whole number")
+                })?,
+            };
+
+            for &(operation, operand) in &self.operations {
+                number = match operation {
+                    Operation::Add => number.checked_add(operand),
+                    Operation::Subtract => number.checked_sub(operand),
+                    Operation::Multiply => number.checked_mul(operand),
+                    Operation::Divide => number.checked_div(operand),
+                    Operation::Remainder => number.checked_rem(operand),
+                }
+                .ok_or_else(|| eyre!("the result overflows, or is divided by zero"))?;
+            }
+
+            value = match (self.padding, counter) {
+                (Some(padding), _) => padding.apply_number(number),
+                (None, Some(_)) => counters.padding.apply_number(number),
+                (None, None) => number.to_string(),
+            };
+        } else if let Some(padding) = &self.padding {
+            value = padding.apply(&value, true);
+        }
+
+        Ok(filter::apply_all(&self.filters, value).into_bytes())
+    }
+}
+
+fn parse_group(text: &str) -> Result<Part> {
+    let (rest, filters) = match text.split_once('|') {
+        Some((rest, filters)) => (rest, Filter::parse_chain(filters)?),
+        None => (text, Vec::new()),
+    };
+    let (rest, padding) = match rest.split_once(':') {
+        Some((rest, spec)) => (
+            rest,
+            Some(
+                Padding::parse(spec)
+                    .ok_or_else(|| eyre!("Invalid format `{spec}` in `${{{text}}}`."))?,
+            ),
+        ),
+        None => (rest, None),
+    };
+    let end = name_length(rest);
+
+    if end == 0 {
+        return Err(eyre!("Missing capture group in `${{{text}}}`."));
+    }
+
+    let mut group = group(&rest[..end]);
+    let mut arithmetic = &rest[end..];
+
+    if let Some(scope) = arithmetic.strip_prefix('@') {
+        let end = name_length(scope);
+
+        if group != Group::Counter(None) || end == 0 {
+            return Err(eyre!(
+                "Only `n` can be counted per group, as in `${{n@1}}`."
+            ));
+        }
+
+        group = Group::Counter(Some(Box::new(self::group(&scope[..end]))));
+        arithmetic = &scope[end..];
+    }
+
+    let mut operations = Vec::new();
+
+    while let Some(c) = arithmetic.chars().next() {
+        let operation = match c {
+            '+' => Operation::Add,
+            '-' => Operation::Subtract,
+            '*' => Operation::Multiply,
+            '/' => Operation::Divide,
+            '%' => Operation::Remainder,
+            _ => return Err(eyre!("Unexpected `{c}` in `${{{text}}}`.")),
+        };
+        let operand = &arithmetic[1..];
+        let length = operand
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(operand.len());
+        let number = operand[..length]
+            .parse()
+            .map_err(|_| eyre!("Expected a number after `{c}` in `${{{text}}}`."))?;
+
+        operations.push((operation, number));
+        arithmetic = &operand[length..];
+    }
+
+    Ok(Part::Group(Expression {
+        source: format!("${{{text}}}"),
+        group,
+        operations,
+        padding,
+        filters,
+    }))
+}
+
+impl Group {
+    /// Whether the group exists in `regex`.
+    fn exists(&self, regex: &Regex) -> bool {
+        match self {
+            Self::Index(i) => *i < regex.captures_len(),
+            Self::Name(name) => regex.capture_names().flatten().any(|x| x == name),
+            Self::Counter(None) => true,
+            Self::Counter(Some(scope)) => scope.exists(regex),
+        }
+    }
+
+    /// The text the group matched, or nothing if it didn't match.
+    fn value<'a>(&self, captures: &Captures<'a>) -> &'a [u8] {
+        let value = match self {
+            Self::Index(i) => captures.get(*i),
+            Self::Name(name) => captures.name(name),
+            Self::Counter(_) => None,
+        };
+
+        value.map_or(&[], |x| x.as_bytes())
+    }
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(i) => write!(f, "{i}"),
+            Self::Name(name) => f.write_str(name),
+            Self::Counter(None) => f.write_str("n"),
+            Self::Counter(Some(scope)) => write!(f, "n@{scope}"),
+        }
+    }
+}
+
+fn group(name: &str) -> Group {
+    if name == "n" {
+        return Group::Counter(None);
+    }
+
+    name.parse()
+        .map_or_else(|_| Group::Name(name.to_owned()), Group::Index)
+}
+
+/// Length of the group name at the start of `text`.
+fn name_length(text: &str) -> usize {
+    text.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(text.len())
+}
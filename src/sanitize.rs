@@ -0,0 +1,250 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Making new names valid on other platforms.
+
+use crate::{
+    limit::{Limit, Unit},
+    name,
+};
+use color_eyre::{eyre::eyre, Result};
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fmt,
+    path::PathBuf,
+};
+
+/// Longest name, in bytes, that every platform accepts.
+pub const MAX_LENGTH: Limit = Limit {
+    length: 255,
+    unit: Unit::Bytes,
+    ellipsis: String::new(),
+};
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Platform that new names have to be valid on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// Linux and other Unix systems, which only disallow `/`
+    Posix,
+    /// Windows, which disallows `<>:"/\|?*` and control characters, names
+    /// ending in dots or spaces, and device names such as `CON`
+    Windows,
+    /// macOS, which disallows `/` and `:`
+    MacOs,
+    /// Everything that is valid on all of the above
+    #[default]
+    Portable,
+}
+
+impl From<&String> for Platform {
+    fn from(a: &String) -> Self {
+        let a = a.to_lowercase();
+
+        match a.as_ref() {
+            "posix" | "unix" | "linux" => Self::Posix,
+            "windows" => Self::Windows,
+            "macos" => Self::MacOs,
+            "portable" => Self::Portable,
+            _ => unreachable!(
+                "If this is reached, something in validation has gone *horribly* wrong."
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Posix => "POSIX",
+            Self::Windows => "Windows",
+            Self::MacOs => "macOS",
+            Self::Portable => "every platform",
+        })
+    }
+}
+
+impl Platform {
+    /// Whether `c` can't be used in a name.
+    const fn disallows(self, c: u8) -> bool {
+        match self {
+            Self::Posix => matches!(c, b'/' | 0),
+            Self::MacOs => matches!(c, b'/' | b':' | 0),
+            Self::Windows | Self::Portable => {
+                matches!(
+                    c,
+                    b'<' | b'>' | b':' | b'"' | b'/' | b'\\' | b'|' | b'?' | b'*'
+                ) || c.is_ascii_control()
+            }
+        }
+    }
+
+    /// Whether names can't end in dots or spaces, or be device names.
+    const fn is_windows(self) -> bool {
+        matches!(self, Self::Windows | Self::Portable)
+    }
+}
+
+/// Changes new names so they are valid on a [`Platform`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sanitizer {
+    /// Platform names have to be valid on
+    pub platform: Platform,
+    /// What disallowed characters are replaced with, unless they have a
+    /// replacement of their own
+    pub replacement: String,
+    /// Replacements for specific characters
+    pub replacements: HashMap<char, String>,
+}
+
+/// A new name that had to be changed by a [`Sanitizer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sanitized {
+    /// The new name as it was planned
+    pub planned: PathBuf,
+    /// The new name it was changed to
+    pub new_path: PathBuf,
+}
+
+impl fmt::Display for Sanitized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[SANITIZE] `{}` will be named `{}` instead.",
+            self.planned.to_string_lossy(),
+            self.new_path.to_string_lossy()
+        )
+    }
+}
+
+impl Sanitizer {
+    /// Replaces disallowed characters with `_`.
+    #[must_use]
+    pub fn new(platform: Platform) -> Self {
+        Self {
+            platform,
+            replacement: "_".to_owned(),
+            replacements: HashMap::new(),
+        }
+    }
+
+    /// Adds a replacement given as `--sanitize-with`: either `C=REPLACEMENT`
+    /// for the character `C`, or a replacement for every other character.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the replacement contains a character that isn't
+    /// allowed itself, or `C` is allowed anyway.
+    pub fn add_replacement(&mut self, value: &str) -> Result<()> {
+        let mut chars = value.chars();
+        let (c, replacement) = match (chars.next(), chars.next()) {
+            (Some(c), Some('=')) => (Some(c), chars.as_str()),
+            _ => (None, value),
+        };
+
+        if let Some(bad) = replacement.bytes().find(|&x| self.platform.disallows(x)) {
+            return Err(eyre!(
+                "The replacement `{replacement}` contains `{}`, which isn't allowed on {}.",
+                bad.escape_ascii(),
+                self.platform
+            ));
+        }
+
+        match c {
+            Some(c) if !u8::try_from(c).is_ok_and(|x| self.platform.disallows(x)) => Err(eyre!(
+                "`{c}` is allowed on {}, so it's never replaced.",
+                self.platform
+            )),
+            Some(c) => {
+                self.replacements.insert(c, replacement.to_owned());
+                Ok(())
+            }
+            None => {
+                replacement.clone_into(&mut self.replacement);
+                Ok(())
+            }
+        }
+    }
+
+    /// Makes `name` valid on the platform.
+    ///
+    /// Disallowed characters are replaced, and on Windows, dots and spaces at
+    /// the end are removed, and device names get the replacement (or `_`)
+    /// after them. Names that are too long are cut short as with
+    /// `--max-length`.
+    #[must_use]
+    pub fn sanitize(&self, name: &OsStr) -> OsString {
+        let result = self.replace(name);
+
+        MAX_LENGTH.truncate(&result, "").unwrap_or(result)
+    }
+
+    /// Makes `name` valid on the platform, except for its length.
+    pub(crate) fn replace(&self, name: &OsStr) -> OsString {
+        let mut result = Vec::new();
+
+        for &c in name::to_bytes(name) {
+            if self.platform.disallows(c) {
+                let replacement = self
+                    .replacements
+                    .get(&char::from(c))
+                    .unwrap_or(&self.replacement);
+
+                result.extend_from_slice(replacement.as_bytes());
+            } else {
+                result.push(c);
+            }
+        }
+
+        if self.platform.is_windows() {
+            while result.last().is_some_and(|&x| x == b'.' || x == b' ') {
+                result.pop();
+            }
+
+            let stem = result
+                .iter()
+                .position(|&x| x == b'.')
+                .unwrap_or(result.len());
+
+            if RESERVED
+                .iter()
+                .any(|x| x.as_bytes().eq_ignore_ascii_case(&result[..stem]))
+            {
+                let suffix = if self.replacement.is_empty() {
+                    "_"
+                } else {
+                    &self.replacement
+                };
+
+                result.splice(stem..stem, suffix.bytes());
+            }
+        }
+
+        name::from_bytes(result).unwrap_or_else(|_| name.to_os_string())
+    }
+}
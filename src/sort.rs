@@ -0,0 +1,187 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Ordering items before they are numbered.
+
+use std::{
+    cmp::Ordering,
+    fs::{self, Metadata},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Order in which items are numbered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// By name, treating runs of digits as numbers (`2` before `10`)
+    #[default]
+    Natural,
+    /// By name, character by character
+    Name,
+    /// By last modification time, oldest first
+    Modified,
+    /// By creation time (or status change time, where creation time isn't
+    /// recorded), oldest first
+    Created,
+    /// By size, smallest first
+    Size,
+    /// By extension, then naturally by name
+    Extension,
+    /// In whatever order the filesystem returns them
+    None,
+}
+
+impl From<&String> for SortOrder {
+    fn from(a: &String) -> Self {
+        let a = a.to_lowercase();
+
+        match a.as_ref() {
+            "natural" => Self::Natural,
+            "name" => Self::Name,
+            "mtime" | "modified" => Self::Modified,
+            "ctime" | "created" => Self::Created,
+            "size" => Self::Size,
+            "extension" | "ext" => Self::Extension,
+            "none" => Self::None,
+            _ => unreachable!(
+                "If this is reached, something in validation has gone *horribly* wrong."
+            ),
+        }
+    }
+}
+
+/// Sorts `items` in the given order, reversing it if asked to.
+///
+/// Ties, and items whose metadata can't be read, fall back to natural order
+/// so the result doesn't depend on the filesystem.
+pub fn sort(items: &mut [PathBuf], order: SortOrder, reverse: bool) {
+    match order {
+        SortOrder::Natural => items.sort_by(|a, b| by_name(a, b)),
+        SortOrder::Name => items.sort_by(|a, b| name(a).cmp(&name(b)).then_with(|| a.cmp(b))),
+        SortOrder::Modified => sort_by_metadata(items, |x| x.modified().ok()),
+        SortOrder::Created => sort_by_metadata(items, created),
+        SortOrder::Size => sort_by_metadata(items, |x| Some(x.len())),
+        SortOrder::Extension => items.sort_by(|a, b| {
+            a.extension()
+                .cmp(&b.extension())
+                .then_with(|| by_name(a, b))
+        }),
+        SortOrder::None => {}
+    }
+
+    if reverse {
+        items.reverse();
+    }
+}
+
+fn sort_by_metadata<K: Ord>(items: &mut [PathBuf], key: impl Fn(&Metadata) -> Option<K>) {
+    let mut keyed = items
+        .iter()
+        .map(|x| {
+            (
+                fs::symlink_metadata(x).ok().and_then(|m| key(&m)),
+                x.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    keyed.sort_by(|(ka, a), (kb, b)| ka.cmp(kb).then_with(|| by_name(a, b)));
+
+    for (slot, (_, x)) in items.iter_mut().zip(keyed) {
+        *slot = x;
+    }
+}
+
+fn created(metadata: &Metadata) -> Option<SystemTime> {
+    metadata.created().ok().or_else(|| {
+        #[cfg(unix)]
+        {
+            use std::{os::unix::fs::MetadataExt, time::Duration};
+
+            let secs = u64::try_from(metadata.ctime()).ok()?;
+            let nanos = u32::try_from(metadata.ctime_nsec()).ok()?;
+
+            SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    })
+}
+
+/// Natural order of the names, then of the whole paths for items that share a
+/// name in different folders.
+fn by_name(a: &Path, b: &Path) -> Ordering {
+    natural_cmp(&name(a), &name(b)).then_with(|| a.cmp(b))
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .map_or_else(String::new, |x| x.to_string_lossy().to_string())
+}
+
+/// Compares two names, treating runs of ASCII digits as numbers.
+///
+/// Letters are compared case-insensitively first, and names that only differ
+/// in case or leading zeroes still get a consistent order.
+#[must_use]
+pub fn natural_cmp(left: &str, right: &str) -> Ordering {
+    let mut lhs = left.chars().peekable();
+    let mut rhs = right.chars().peekable();
+
+    loop {
+        let ordering = match (lhs.peek(), rhs.peek()) {
+            (None, None) => return left.cmp(right),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let l = take_number(&mut lhs);
+                let r = take_number(&mut rhs);
+                let (l, r) = (l.trim_start_matches('0'), r.trim_start_matches('0'));
+
+                l.len().cmp(&r.len()).then_with(|| l.cmp(r))
+            }
+            (Some(l), Some(r)) => {
+                let ordering = l.to_lowercase().cmp(r.to_lowercase());
+
+                lhs.next();
+                rhs.next();
+                ordering
+            }
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut number = String::new();
+
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        number.push(c);
+    }
+
+    number
+}
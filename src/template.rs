@@ -0,0 +1,414 @@
+/*
+MIT License
+Copyright (c) 2020-2023 Lyssieth
+
+Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the "Software"), to deal
+in the Software without restriction, including without limitation the rights
+to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+copies of the Software, and to permit persons to whom the Software is
+furnished to do so, subject to the following conditions:
+
+The above copyright notice and this permission notice shall be included in all
+copies or substantial portions of the Software.
+
+THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+SOFTWARE.
+*/
+//! Templates that new names are built from.
+
+use crate::{
+    filter::{self, Filter},
+    name,
+};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+use color_eyre::{eyre::eyre, Result};
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::{self, Write},
+    fs::{self, Metadata},
+    path::Path,
+    str::FromStr,
+};
+
+/// A template for new names, such as `{parent}-{n:03}{ext}`.
+///
+/// Text is kept as it is, except for variables in braces, which are replaced
+/// by a value of the item being renamed. `{{` and `}}` stand for literal
+/// braces. The following variables are available:
+///
+/// - `{n}`: the item's number
+/// - `{prefix}`: the prefix given with `--prefix`
+/// - `{name}`: the item's whole name
+/// - `{stem}`: the item's name, without its extension
+/// - `{ext}`: the item's extension, with its leading `.`, or nothing
+/// - `{parent}`: the name of the directory the item is in
+/// - `{size}`: the item's size in bytes
+/// - `{mtime}`: when the item was last modified
+///
+/// A variable can be followed by a format after a `:`. For `{mtime}`, it's a
+/// `strftime` format, which defaults to `%Y-%m-%d`. For every other variable,
+/// it's a width the value is padded to, optionally preceded by a fill
+/// character and an alignment (`<`, `^` or `>`), as in `{n:0>3}` or
+/// `{stem:_<20}`. `{n:03}` is short for `{n:0>3}`. Numbers are aligned right
+/// and everything else left, unless stated otherwise.
+///
+/// Finally, a variable can be transformed by filters after a `|`, as in
+/// `{stem|lower|slug}` or `{name|truncate:40}`. The filters are `lower`,
+/// `upper`, `title`, `trim`, `ascii[:FALLBACK]`, `slug[:SEPARATOR]`,
+/// `pad:WIDTH[:FILL]`, `truncate:LENGTH` and `replace:FROM:TO`.
+///
+/// Names that aren't valid UTF-8 are kept as they are, but can't be formatted
+/// or filtered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Variable(Variable, Format, Vec<Filter>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    Number,
+    Prefix,
+    Name,
+    Stem,
+    Ext,
+    Parent,
+    Size,
+    Modified,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Format {
+    None,
+    Pad(Padding),
+    Time(String),
+}
+
+/// How a value is padded to a minimum width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Padding {
+    fill: char,
+    align: Option<Align>,
+    width: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Template {
+    /// Parses a template, checking that every variable and format in it is
+    /// valid.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a brace isn't closed or opened, a variable doesn't
+    /// exist, or a format is invalid for its variable.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| eyre!("Unclosed `{{` in template `{source}`."))?;
+
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(parse_variable(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(eyre!("Unmatched `}}` in template `{source}`.")),
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self {
+            source: source.to_owned(),
+            parts,
+        })
+    }
+
+    /// Builds the new name for the item at `path`, numbered `number`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the template needs the item's metadata, and it
+    /// can't be read, or a part of the name that isn't valid UTF-8 would have
+    /// to be formatted or filtered.
+    pub fn render(&self, path: &Path, number: usize, prefix: &str) -> Result<OsString> {
+        let mut metadata = None;
+        let mut result = Vec::new();
+
+        for part in &self.parts {
+            let (variable, format, filters) = match part {
+                Part::Text(text) => {
+                    result.extend_from_slice(text.as_bytes());
+                    continue;
+                }
+                Part::Variable(variable, format, filters) => (variable, format, filters),
+            };
+            let name = match variable {
+                Variable::Name => Some(bytes(path.file_name())),
+                Variable::Stem => Some(bytes(path.file_stem())),
+                Variable::Ext => Some(
+                    path.extension()
+                        .map_or_else(Vec::new, |x| [b".", name::to_bytes(x)].concat()),
+                ),
+                Variable::Parent => Some(bytes(parent_name(path).as_deref())),
+                _ => None,
+            };
+
+            // Parts of the name that are used as they are don't have to be
+            // text.
+            if let Some(name) = &name {
+                if *format == Format::None && filters.is_empty() {
+                    result.extend_from_slice(name);
+                    continue;
+                }
+            }
+
+            let value = match variable {
+                Variable::Number => number.to_string(),
+                Variable::Prefix => prefix.to_owned(),
+                Variable::Name | Variable::Stem | Variable::Ext | Variable::Parent => {
+                    let label = match variable {
+                        Variable::Name => "name",
+                        Variable::Stem => "stem",
+                        Variable::Ext => "ext",
+                        _ => "parent",
+                    };
+                    let what = format!("`{{{label}}}` for `{}`", path.to_string_lossy());
+
+                    name::decode(name.as_deref().unwrap_or_default(), &what)?.to_owned()
+                }
+                Variable::Size => read_metadata(&mut metadata, path)?.len().to_string(),
+                Variable::Modified => {
+                    let time =
+                        DateTime::<Local>::from(read_metadata(&mut metadata, path)?.modified()?);
+                    let format = match format {
+                        Format::Time(format) => format.as_str(),
+                        _ => "%Y-%m-%d",
+                    };
+                    let mut value = String::new();
+
+                    write!(value, "{}", time.format(format))?;
+                    value
+                }
+            };
+
+            let value = match format {
+                Format::Pad(padding) => {
+                    let numeric = matches!(variable, Variable::Number | Variable::Size);
+
+                    padding.apply(&value, numeric)
+                }
+                Format::None | Format::Time(_) => value,
+            };
+
+            result.extend_from_slice(filter::apply_all(filters, value).as_bytes());
+        }
+
+        name::from_bytes(result)
+    }
+}
+
+impl FromStr for Template {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Padding {
+    /// Parses a format such as `0>3`, `^10` or `03`.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let align = |c| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+        let mut chars = spec.chars();
+        let first = chars.next();
+        let second = chars.next();
+
+        let (fill, align, width) = match (first, second.and_then(align)) {
+            (Some(fill), Some(a)) => (fill, Some(a), &spec[fill.len_utf8() + 1..]),
+            _ => match first.and_then(align) {
+                Some(a) => (' ', Some(a), &spec[1..]),
+                None if spec.len() > 1 && spec.starts_with('0') => {
+                    ('0', Some(Align::Right), &spec[1..])
+                }
+                None => (' ', None, spec),
+            },
+        };
+        let width = if width.is_empty() && align.is_some() {
+            0
+        } else {
+            width.parse().ok()?
+        };
+
+        Some(Self { fill, align, width })
+    }
+
+    /// Pads `number`, keeping its sign in front of any zeros.
+    pub fn apply_number(&self, number: i64) -> String {
+        if number < 0 && self.fill == '0' {
+            let padded = Self {
+                width: self.width.saturating_sub(1),
+                ..*self
+            };
+
+            format!(
+                "-{}",
+                padded.apply(&number.unsigned_abs().to_string(), true)
+            )
+        } else {
+            self.apply(&number.to_string(), true)
+        }
+    }
+
+    /// Pads `value`, aligning it right by default if it's `numeric`.
+    pub fn apply(&self, value: &str, numeric: bool) -> String {
+        let length = value.chars().count();
+
+        if length >= self.width {
+            return value.to_owned();
+        }
+
+        let missing = self.width - length;
+        let align = self
+            .align
+            .unwrap_or(if numeric { Align::Right } else { Align::Left });
+        let (left, right) = match align {
+            Align::Left => (0, missing),
+            Align::Center => (missing / 2, missing - missing / 2),
+            Align::Right => (missing, 0),
+        };
+        let fill = |n| std::iter::repeat_n(self.fill, n);
+
+        fill(left).chain(value.chars()).chain(fill(right)).collect()
+    }
+}
+
+fn parse_variable(text: &str) -> Result<Part> {
+    let (text, filters) = match text.split_once('|') {
+        Some((text, filters)) => (text, Filter::parse_chain(filters)?),
+        None => (text, Vec::new()),
+    };
+    let (name, spec) = match text.split_once(':') {
+        Some((name, spec)) => (name, Some(spec)),
+        None => (text, None),
+    };
+    let variable = match name.trim() {
+        "n" => Variable::Number,
+        "prefix" => Variable::Prefix,
+        "name" => Variable::Name,
+        "stem" => Variable::Stem,
+        "ext" => Variable::Ext,
+        "parent" => Variable::Parent,
+        "size" => Variable::Size,
+        "mtime" => Variable::Modified,
+        _ => return Err(eyre!("Unknown variable `{{{name}}}` in template.")),
+    };
+    let format = match (spec, variable) {
+        (None, _) => Format::None,
+        (Some(spec), Variable::Modified) => {
+            if StrftimeItems::new(spec).any(|x| matches!(x, Item::Error)) {
+                return Err(eyre!("Invalid time format `{spec}` for `{{{name}}}`."));
+            }
+
+            Format::Time(spec.to_owned())
+        }
+        (Some(spec), _) => Format::Pad(
+            Padding::parse(spec)
+                .ok_or_else(|| eyre!("Invalid format `{spec}` for `{{{name}}}`."))?,
+        ),
+    };
+
+    Ok(Part::Variable(variable, format, filters))
+}
+
+/// Reads the metadata of `path` the first time it's needed.
+fn read_metadata<'a>(cache: &'a mut Option<Metadata>, path: &Path) -> Result<&'a Metadata> {
+    if let Some(metadata) = cache {
+        return Ok(metadata);
+    }
+
+    let metadata = fs::symlink_metadata(path).map_err(|e| {
+        eyre!(
+            "Unable to read metadata of {}: {}",
+            path.to_string_lossy(),
+            e
+        )
+    })?;
+
+    Ok(cache.insert(metadata))
+}
+
+/// Name of the directory `path` is in, which is looked up when the folder
+/// was given as `.` or `..`.
+fn parent_name(path: &Path) -> Option<OsString> {
+    let parent = path
+        .parent()
+        .filter(|x| !x.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    parent.file_name().map_or_else(
+        || {
+            parent
+                .canonicalize()
+                .ok()?
+                .file_name()
+                .map(OsStr::to_os_string)
+        },
+        |x| Some(x.to_os_string()),
+    )
+}
+
+fn bytes(value: Option<&OsStr>) -> Vec<u8> {
+    value.map_or_else(Vec::new, |x| name::to_bytes(x).to_vec())
+}
@@ -1,10 +1,21 @@
-use crate::{run, Arguments};
+use crate::{
+    atomic::rename_no_replace,
+    conflict, execute,
+    execute::Step,
+    filter::{apply_all, Filter},
+    journal::JournalWriter,
+    natural_cmp, plan, resume, run, undo, Arguments, Case, ConflictPolicy, CounterScope, Limit,
+    Normalization, Platform, Problem, RenameItem, RenamePlan, Resolution, ResumeArguments, Rule,
+    Sanitizer, SortOrder, Template, UndoArguments,
+};
 use std::{
-    fs::File,
+    ffi::OsStr,
+    fs::{self, File},
+    io::ErrorKind,
     path::{Path, PathBuf},
 };
 
-use regex::Regex;
+use regex::bytes::{Regex, RegexBuilder};
 use tempfile::tempdir;
 
 fn setup_files_for_test(path: &Path, filenames: &[&str]) {
@@ -133,3 +144,1685 @@ fn usage_for_shows() {
 
     check_filenames(path.path(), SHOWS_EXPECTED);
 }
+
+// Planning must not touch the disk, and the plan can be trimmed before execution.
+#[test]
+fn plan_then_execute() {
+    const PLAN_FILENAMES: &[&str] = &["a.txt", "b.txt"];
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), PLAN_FILENAMES);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^(\w)\.txt$").expect("failed to compile regex")),
+        match_rename: Some("renamed_${1}.txt".to_owned()),
+        ..Arguments::default()
+    };
+
+    let mut plan = plan(&args).expect("failed to plan");
+
+    assert_eq!(plan.items.len(), 2);
+    assert!(plan.problems.is_empty());
+    check_filenames(path.path(), PLAN_FILENAMES);
+
+    plan.items
+        .retain(|x| x.original_path.file_name() == Some("a.txt".as_ref()));
+
+    execute(plan).expect("failed to execute");
+
+    check_filenames(path.path(), &["renamed_a.txt", "b.txt"]);
+}
+
+// Collisions are reported up front, and nothing is renamed while there are any.
+#[test]
+fn collisions_block_execution() {
+    const COLLISION_FILENAMES: &[&str] = &["a_1.txt", "a_2.txt", "b_1.txt", "b.txt", "c_1.txt"];
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), COLLISION_FILENAMES);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^(\w)_\d\.txt$").expect("failed to compile regex")),
+        match_rename: Some("${1}.txt".to_owned()),
+        ..Arguments::default()
+    };
+
+    let plan = plan(&args).expect("failed to plan");
+
+    assert_eq!(plan.problems.len(), 2);
+    assert!(plan.problems.iter().any(|x| matches!(
+        x,
+        Problem::DuplicateTarget { target, sources }
+            if target == &path.path().join("a.txt") && sources.len() == 2
+    )));
+    assert!(plan.problems.iter().any(|x| matches!(
+        x,
+        Problem::TargetExists(item) if item.new_path == path.path().join("b.txt")
+    )));
+
+    assert!(run(args).is_err());
+
+    check_filenames(path.path(), COLLISION_FILENAMES);
+    assert!(!path.path().join("c.txt").exists());
+}
+
+// rena --origin 1 images/, on a folder that rena already numbered from 0
+#[test]
+fn renumber_own_output() {
+    const RENUMBER_FILENAMES: &[&str] = &[
+        "item_0000000000.jpg",
+        "item_0000000001.jpg",
+        "item_0000000002.jpg",
+    ];
+    const RENUMBER_EXPECTED: &[&str] = &[
+        "item_0000000001.jpg",
+        "item_0000000002.jpg",
+        "item_0000000003.jpg",
+    ];
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), RENUMBER_FILENAMES);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        prefix: "item".to_owned(),
+        padding: 10,
+        origin: 1,
+        ..Arguments::default()
+    };
+
+    let res = run(args);
+
+    assert!(res.is_ok());
+
+    check_filenames(path.path(), RENUMBER_EXPECTED);
+    assert!(!path.path().join("item_0000000000.jpg").exists());
+    assert_eq!(path.path().read_dir().expect("failed to read").count(), 3);
+}
+
+// Swapping two names needs a temporary name to break the cycle.
+#[test]
+fn swap_names() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let a = path.path().join("a.txt");
+    let b = path.path().join("b.txt");
+
+    fs::write(&a, "a").expect("failed to write file");
+    fs::write(&b, "b").expect("failed to write file");
+
+    let plan = RenamePlan {
+        items: vec![
+            RenameItem {
+                original_path: a.clone(),
+                new_path: b.clone(),
+                ..RenameItem::default()
+            },
+            RenameItem {
+                original_path: b.clone(),
+                new_path: a.clone(),
+                ..RenameItem::default()
+            },
+        ],
+        ..RenamePlan::default()
+    };
+
+    assert!(plan.validate().is_empty());
+
+    execute(plan).expect("failed to execute");
+
+    assert_eq!(fs::read_to_string(&a).expect("failed to read file"), "b");
+    assert_eq!(fs::read_to_string(&b).expect("failed to read file"), "a");
+    assert_eq!(path.path().read_dir().expect("failed to read").count(), 2);
+}
+
+// A failure halfway through a transactional run leaves the folder untouched.
+#[test]
+fn transactional_rollback() {
+    const ROLLBACK_FILENAMES: &[&str] = &["a.txt", "b.txt"];
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), ROLLBACK_FILENAMES);
+
+    let items = vec![
+        RenameItem {
+            original_path: path.path().join("a.txt"),
+            new_path: path.path().join("c.txt"),
+            ..RenameItem::default()
+        },
+        RenameItem {
+            original_path: path.path().join("missing.txt"),
+            new_path: path.path().join("d.txt"),
+            ..RenameItem::default()
+        },
+    ];
+
+    let plan = RenamePlan {
+        items: items.clone(),
+        transactional: true,
+        ..RenamePlan::default()
+    };
+
+    assert!(execute(plan).is_err());
+
+    check_filenames(path.path(), ROLLBACK_FILENAMES);
+    assert!(!path.path().join("c.txt").exists());
+
+    let plan = RenamePlan {
+        items,
+        ..RenamePlan::default()
+    };
+
+    assert!(execute(plan).is_ok());
+
+    check_filenames(path.path(), &["c.txt", "b.txt"]);
+}
+
+// rena images/ && rena undo
+#[test]
+fn undo_last_run() {
+    const UNDO_FILENAMES: &[&str] = &["image.jpg", "image3.jpg", "item_0000000000.jpg"];
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let journal = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), UNDO_FILENAMES);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        prefix: "item".to_owned(),
+        padding: 10,
+        journal: Some(PathBuf::from(journal.path())),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+    assert!(!path.path().join("image.jpg").exists());
+
+    let args = UndoArguments {
+        journal: PathBuf::from(journal.path()),
+        ..UndoArguments::default()
+    };
+
+    assert!(undo(&args).is_ok());
+
+    check_filenames(path.path(), UNDO_FILENAMES);
+    assert_eq!(path.path().read_dir().expect("failed to read").count(), 3);
+
+    // The run has been undone, so there is nothing left to undo.
+    assert!(undo(&args).is_err());
+}
+
+// Undoing refuses to move anything if an item isn't where the run left it.
+#[test]
+fn undo_refuses_moved_items() {
+    const MOVED_FILENAMES: &[&str] = &["a.jpg", "b.jpg"];
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let journal = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), MOVED_FILENAMES);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        prefix: "item".to_owned(),
+        padding: 1,
+        journal: Some(PathBuf::from(journal.path())),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    fs::rename(
+        path.path().join("item_0.jpg"),
+        path.path().join("moved.jpg"),
+    )
+    .expect("failed to move file");
+
+    let args = UndoArguments {
+        journal: PathBuf::from(journal.path()),
+        ..UndoArguments::default()
+    };
+
+    assert!(undo(&args).is_err());
+
+    check_filenames(path.path(), &["item_1.jpg", "moved.jpg"]);
+}
+
+// Sets up a run of `a.txt -> x.txt, b.txt -> y.txt, c.txt -> z.txt` that was
+// stopped right after moving `b.txt`, before that move was recorded.
+fn setup_interrupted_run(path: &Path, journal: &Path) {
+    setup_files_for_test(path, &["a.txt", "b.txt", "c.txt"]);
+
+    let steps = [("a.txt", "x.txt"), ("b.txt", "y.txt"), ("c.txt", "z.txt")]
+        .iter()
+        .map(|(from, to)| Step {
+            from: path.join(from),
+            to: path.join(to),
+            overwrite: false,
+        })
+        .collect::<Vec<_>>();
+    let mut writer =
+        JournalWriter::create(journal, path, &steps).expect("failed to create journal");
+
+    fs::rename(&steps[0].from, &steps[0].to).expect("failed to move file");
+    writer
+        .record(&steps[0].from, &steps[0].to)
+        .expect("failed to record move");
+    fs::rename(&steps[1].from, &steps[1].to).expect("failed to move file");
+}
+
+// rena resume images/
+#[test]
+fn resume_interrupted_run() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let journal = tempdir().expect("failed to obtain temporary directory");
+    setup_interrupted_run(path.path(), journal.path());
+
+    // Interrupted runs have to be resumed before they can be undone.
+    let args = UndoArguments {
+        journal: PathBuf::from(journal.path()),
+        ..UndoArguments::default()
+    };
+
+    assert!(undo(&args).is_err());
+
+    let args = ResumeArguments {
+        journal: PathBuf::from(journal.path()),
+        folder: PathBuf::from(path.path()),
+        ..ResumeArguments::default()
+    };
+
+    assert!(resume(&args).is_ok());
+
+    check_filenames(path.path(), &["x.txt", "y.txt", "z.txt"]);
+    assert_eq!(path.path().read_dir().expect("failed to read").count(), 3);
+
+    // Nothing is left to resume, but the run can now be undone.
+    assert!(resume(&args).is_err());
+
+    let args = UndoArguments {
+        journal: PathBuf::from(journal.path()),
+        ..UndoArguments::default()
+    };
+
+    assert!(undo(&args).is_ok());
+
+    check_filenames(path.path(), &["a.txt", "b.txt", "c.txt"]);
+}
+
+// rena resume --rollback images/
+#[test]
+fn resume_with_rollback() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let journal = tempdir().expect("failed to obtain temporary directory");
+    setup_interrupted_run(path.path(), journal.path());
+
+    let args = ResumeArguments {
+        journal: PathBuf::from(journal.path()),
+        folder: PathBuf::from(path.path()),
+        rollback: true,
+        ..ResumeArguments::default()
+    };
+
+    assert!(resume(&args).is_ok());
+
+    check_filenames(path.path(), &["a.txt", "b.txt", "c.txt"]);
+    assert_eq!(path.path().read_dir().expect("failed to read").count(), 3);
+    assert!(resume(&args).is_err());
+}
+
+// rena --match "^(\w)_\d\.txt$" --match-rename "${1}.txt" --on-conflict <policy> files/
+#[test]
+fn conflict_policies() {
+    const CONFLICT_FILENAMES: &[&str] = &["a_1.txt", "a_2.txt", "b_1.txt", "b.txt"];
+
+    let setup = |policy| {
+        let path = tempdir().expect("failed to obtain temporary directory");
+        setup_files_for_test(path.path(), CONFLICT_FILENAMES);
+        fs::write(path.path().join("b_1.txt"), "new").expect("failed to write file");
+
+        let args = Arguments {
+            folder: PathBuf::from(path.path()),
+            match_regex: Some(Regex::new(r"^(\w)_\d\.txt$").expect("failed to compile regex")),
+            match_rename: Some("${1}.txt".to_owned()),
+            on_conflict: policy,
+            ..Arguments::default()
+        };
+
+        assert!(run(args).is_ok());
+
+        path
+    };
+
+    let path = setup(ConflictPolicy::Skip);
+    check_filenames(path.path(), &["a.txt", "a_2.txt", "b_1.txt", "b.txt"]);
+
+    let path = setup(ConflictPolicy::AutoSuffix);
+    check_filenames(path.path(), &["a.txt", "a (1).txt", "b (1).txt", "b.txt"]);
+
+    let path = setup(ConflictPolicy::Overwrite);
+    check_filenames(path.path(), &["a_1.txt", "a.txt", "b.txt"]);
+    assert_eq!(
+        fs::read_to_string(path.path().join("b.txt")).expect("failed to read file"),
+        "new"
+    );
+
+    let path = setup(ConflictPolicy::KeepLarger);
+    check_filenames(path.path(), &["a.txt", "a_2.txt", "b.txt"]);
+    assert!(!path.path().join("b_1.txt").exists());
+}
+
+// Renames never replace something that appeared at the target in the meantime.
+#[test]
+fn no_replace_rename() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let a = path.path().join("a.txt");
+    let b = path.path().join("b.txt");
+
+    fs::write(&a, "a").expect("failed to write file");
+    fs::write(&b, "b").expect("failed to write file");
+
+    let err = rename_no_replace(&a, &b).expect_err("rename replaced a file");
+
+    assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(fs::read_to_string(&a).expect("failed to read file"), "a");
+    assert_eq!(fs::read_to_string(&b).expect("failed to read file"), "b");
+
+    let c = path.path().join("c.txt");
+
+    rename_no_replace(&a, &c).expect("failed to rename");
+
+    assert!(!a.exists());
+    assert_eq!(fs::read_to_string(&c).expect("failed to read file"), "a");
+}
+
+// rena images/ && rena --sort size --reverse images/
+#[test]
+fn sorted_numbering() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+
+    for (name, contents) in [("img10.jpg", "ab"), ("img2.jpg", "abc"), ("IMG1.jpg", "a")] {
+        fs::write(path.path().join(name), contents).expect("failed to write file");
+    }
+
+    let read = |name: &str| fs::read_to_string(path.path().join(name)).expect("failed to read");
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        prefix: "item".to_owned(),
+        padding: 1,
+        ..Arguments::default()
+    };
+
+    assert!(run(args.clone()).is_ok());
+    assert_eq!(read("item_0.jpg"), "a");
+    assert_eq!(read("item_1.jpg"), "abc");
+    assert_eq!(read("item_2.jpg"), "ab");
+
+    let args = Arguments {
+        prefix: "sized".to_owned(),
+        sort: SortOrder::Size,
+        reverse: true,
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+    assert_eq!(read("sized_0.jpg"), "abc");
+    assert_eq!(read("sized_1.jpg"), "ab");
+    assert_eq!(read("sized_2.jpg"), "a");
+}
+
+#[test]
+fn natural_order() {
+    let mut names = ["a10", "a2", "A1", "a02", "b", "a"];
+
+    names.sort_by(|a, b| natural_cmp(a, b));
+
+    assert_eq!(names, ["a", "A1", "a02", "a2", "a10", "b"]);
+}
+
+// rena --recursive --counter directory --min-depth 2 --max-depth 2 photos/
+#[test]
+fn recursive_rename() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+
+    for dir in ["2022", "2023", "2023/raw/edited"] {
+        fs::create_dir_all(path.path().join(dir)).expect("failed to create directory");
+    }
+
+    setup_files_for_test(
+        path.path(),
+        &[
+            "top.jpg",
+            "2022/b.jpg",
+            "2022/a.jpg",
+            "2023/c.jpg",
+            "2023/raw/d.jpg",
+            "2023/raw/edited/e.jpg",
+        ],
+    );
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        prefix: "item".to_owned(),
+        padding: 1,
+        recursive: true,
+        ..Arguments::default()
+    };
+
+    assert!(run(args.clone()).is_ok());
+
+    check_filenames(
+        path.path(),
+        &[
+            "item_0.jpg",
+            "2022/item_1.jpg",
+            "2022/item_2.jpg",
+            "2023/item_3.jpg",
+            "2023/raw/item_4.jpg",
+            "2023/raw/edited/item_5.jpg",
+        ],
+    );
+
+    let args = Arguments {
+        prefix: "photo".to_owned(),
+        counter: CounterScope::Directory,
+        min_depth: 2,
+        max_depth: Some(2),
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(
+        path.path(),
+        &[
+            "item_0.jpg",
+            "2022/photo_0.jpg",
+            "2022/photo_1.jpg",
+            "2023/photo_0.jpg",
+            "2023/raw/item_4.jpg",
+            "2023/raw/edited/item_5.jpg",
+        ],
+    );
+}
+
+// rena --dir --recursive shows/ && rena undo
+#[test]
+fn recursive_directories() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let journal = tempdir().expect("failed to obtain temporary directory");
+
+    fs::create_dir_all(path.path().join("a/b/c")).expect("failed to create directory");
+    fs::create_dir_all(path.path().join("a/d")).expect("failed to create directory");
+    setup_files_for_test(path.path(), &["a/b/c/file.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        directory: true,
+        prefix: "dir".to_owned(),
+        padding: 1,
+        recursive: true,
+        journal: Some(PathBuf::from(journal.path())),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["dir_0/dir_1/dir_3/file.txt", "dir_0/dir_2"]);
+    assert!(!path.path().join("a").exists());
+
+    let args = UndoArguments {
+        journal: PathBuf::from(journal.path()),
+        ..UndoArguments::default()
+    };
+
+    assert!(undo(&args).is_ok());
+
+    check_filenames(path.path(), &["a/b/c/file.txt", "a/d"]);
+    assert!(!path.path().join("dir_0").exists());
+}
+
+// rena --template "{parent}-{n:03}{ext}" photos/
+#[test]
+#[allow(clippy::literal_string_with_formatting_args)]
+fn template_rename() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let folder = path.path().join("photos");
+
+    fs::create_dir(&folder).expect("failed to create directory");
+    setup_files_for_test(&folder, &["b.jpg", "a.png", "notes"]);
+    fs::write(folder.join("notes"), "12345").expect("failed to write file");
+
+    let args = Arguments {
+        folder: folder.clone(),
+        origin: 1,
+        template: Some(Template::parse("{parent}-{n:03}{ext}").expect("failed to parse")),
+        ..Arguments::default()
+    };
+
+    assert!(run(args.clone()).is_ok());
+
+    check_filenames(&folder, &["photos-001.png", "photos-002.jpg", "photos-003"]);
+
+    let year = chrono::Local::now().format("%Y").to_string();
+    let args = Arguments {
+        template: Some(
+            Template::parse("{{{mtime:%Y}}} {stem:_^12}.{size}{ext}").expect("failed to parse"),
+        ),
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(
+        &folder,
+        &[
+            format!("{{{year}}} _photos-001_.0.png").as_str(),
+            format!("{{{year}}} _photos-002_.0.jpg").as_str(),
+            format!("{{{year}}} _photos-003_.5").as_str(),
+        ],
+    );
+
+    for broken in ["{n", "n}", "{nope}", "{n:x}", "{mtime:%Q}"] {
+        assert!(Template::parse(broken).is_err(), "`{broken}` parsed");
+    }
+}
+
+// rena --match "(\w+) - (\d+)\.(\w+)" --match-rename "${1|title|replace:_: } ${2|pad:3}.${3|lower}" books/
+#[test]
+#[allow(clippy::literal_string_with_formatting_args)]
+fn replacement_filters() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["the_long_way - 7.PDF", "Kept As Is.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"(\w+) - (\d+)\.(\w+)").expect("failed to compile regex")),
+        match_rename: Some("${1|title|replace:_: } ${2|pad:3}.${3|lower}$$".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["The Long Way 007.pdf$", "Kept As Is.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        template: Some(
+            Template::parse("{stem|slug|truncate:8}{ext|upper}").expect("failed to parse"),
+        ),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["kept-as-.TXT", "the-long.PDF$"]);
+}
+
+#[test]
+fn filter_chains() {
+    let cases = [
+        ("lower", "MiXeD Case", "mixed case"),
+        ("upper", "MiXeD Case", "MIXED CASE"),
+        ("title", "o'neil's LONG-day", "O'neil's Long-Day"),
+        ("title", "a tale of two cities", "A Tale of Two Cities"),
+        ("trim", "  padded \t", "padded"),
+        ("slug", "Hello, World! (2023)", "hello-world-2023"),
+        ("slug", "Caf\u{e9} \u{dc}n\u{ef}code", "cafe-unicode"),
+        ("slug:_", "\u{ff26}ull \u{3000}width", "full_width"),
+        ("ascii", "\u{5317}\u{4eac}.txt", "Bei Jing.txt"),
+        ("ascii:?", "\u{10ffff}x", "?x"),
+        ("pad:3", "7", "007"),
+        ("pad:4:_", "ab", "__ab"),
+        ("pad:1", "123", "123"),
+        ("truncate:3", "ünïcode", "ünï"),
+        ("replace:_: ", "a_b_c", "a b c"),
+        ("trim|upper|replace:O:0|truncate:4", " foo bar ", "F00 "),
+    ];
+
+    for (chain, input, expected) in cases {
+        let filters = Filter::parse_chain(chain).expect("failed to parse filters");
+
+        assert_eq!(apply_all(&filters, input.to_owned()), expected, "{chain}");
+    }
+
+    for broken in [
+        "nope",
+        "pad",
+        "pad:x",
+        "pad:2:ab",
+        "truncate",
+        "replace::x",
+        "lower:1",
+        "ascii:a:b",
+    ] {
+        assert!(Filter::parse_chain(broken).is_err(), "`{broken}` parsed");
+    }
+}
+
+// rena --match "Show - (\d+)" --match-rename "Show S${1-1/12+1:02}E${1-1%12+1:02}" Show/
+#[test]
+fn capture_arithmetic() {
+    const EPISODE_FILENAMES: &[&str] = &["Show - 1.mkv", "Show - 12.mkv", "Show - 13.mkv"];
+    const EPISODE_EXPECTED: &[&str] = &["Show S01E01.mkv", "Show S01E12.mkv", "Show S02E01.mkv"];
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), EPISODE_FILENAMES);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"Show - (\d+)").expect("failed to compile regex")),
+        match_rename: Some("Show S${1-1/12+1:02}E${1-1%12+1:02}".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), EPISODE_EXPECTED);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^Show S(\w+)E(\d+)").expect("failed to compile regex")),
+        match_rename: Some("${2*100+1:04}-${1-3:>3}${1-3:03}".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(
+        path.path(),
+        &["0101- -2-02.mkv", "1201- -2-02.mkv", "0101- -1-01.mkv"],
+    );
+}
+
+// rena --match "page(\w+)" --match-rename "page${1+100}" book/
+#[test]
+#[allow(clippy::literal_string_with_formatting_args)]
+fn capture_arithmetic_errors() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["page001.jpg", "pagexx.jpg"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"page(\w+)").expect("failed to compile regex")),
+        match_rename: Some("page${1+100}".to_owned()),
+        ..Arguments::default()
+    };
+
+    let error = plan(&args).expect_err("a non-numeric capture to be refused");
+
+    assert!(error.to_string().contains("`xx`"), "{error}");
+    check_filenames(path.path(), &["page001.jpg", "pagexx.jpg"]);
+
+    for broken in ["${1+}", "${1+x}", "${1^2}", "${+1}", "${1:x}"] {
+        let args = Arguments {
+            match_rename: Some(broken.to_owned()),
+            ..args.clone()
+        };
+
+        assert!(plan(&args).is_err(), "`{broken}` parsed");
+    }
+
+    let args = Arguments {
+        match_rename: Some("page${1/0}".to_owned()),
+        ..args
+    };
+
+    assert!(plan(&args).is_err());
+}
+
+// rena --origin 1 --match "s(\d) - \w+" --match-rename "S${1:02}E${n@1:02}" Show/
+#[test]
+#[allow(clippy::literal_string_with_formatting_args)]
+fn regex_counters() {
+    const COUNTER_FILENAMES: &[&str] = &["s1 - b.mkv", "s1 - a.mkv", "s2 - c.mkv", "s1 - c.mkv"];
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), COUNTER_FILENAMES);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        origin: 1,
+        padding: 3,
+        match_regex: Some(Regex::new(r"s(\d) - (\w+)").expect("failed to compile regex")),
+        match_rename: Some("S${1:02}E${n@1:02} $2".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(run(args.clone()).is_ok());
+
+    check_filenames(
+        path.path(),
+        &[
+            "S01E01 a.mkv",
+            "S01E02 b.mkv",
+            "S01E03 c.mkv",
+            "S02E01 c.mkv",
+        ],
+    );
+
+    let args = Arguments {
+        match_regex: Some(Regex::new(r"S\d+E\d+ (\w)").expect("failed to compile regex")),
+        match_rename: Some("$n-${n*10}-$1".to_owned()),
+        sort: SortOrder::Name,
+        reverse: true,
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(
+        path.path(),
+        &[
+            "001-010-c.mkv",
+            "002-020-c.mkv",
+            "003-030-b.mkv",
+            "004-040-a.mkv",
+        ],
+    );
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"(\d+)").expect("failed to compile regex")),
+        match_rename: Some("${1@1}".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(plan(&args).is_err());
+}
+
+// rena --rule 's/\./ /ge' --rule 's/ +/ /g' --rule 's#^the#The#i' Shows/
+#[test]
+fn substitution_rules() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["the.Show..Name.2020.mkv", "THE other.file"]);
+
+    let rules = ["s/\\./ /ge", "s/ +/ /g", "s#^the#The#i"]
+        .into_iter()
+        .map(|x| Rule::parse(x).expect("failed to parse rule"))
+        .collect();
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        rules,
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["The Show Name 2020.mkv", "The other.file"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"\s").expect("failed to compile regex")),
+        match_rename: Some("_".to_owned()),
+        replace_all: true,
+        rules: vec![Rule::parse(r"s/_(\d+)/-$1/").expect("failed to parse rule")],
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["The_Show_Name-2020.mkv", "The_other.file"]);
+
+    let rule = Rule::parse(r"s|a\|b|x\y|").expect("failed to parse rule");
+
+    assert_eq!(rule.regex.as_str(), "a|b");
+    assert_eq!(rule.replacement, r"x\y");
+
+    for broken in [
+        "x/a/b/", "s/a/b", "s/a/b/c/", "s/a/b/q", "s/(/b/", "sab", "s",
+    ] {
+        assert!(Rule::parse(broken).is_err(), "`{broken}` parsed");
+    }
+}
+
+// rena --ignore-case --match "s(?P<season>\d+)e(?P<episode>\d+)" --match-rename "Season ${season} - ${episode|pad:3}" Show/
+#[test]
+fn named_groups() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["s1e2.mkv", "S02E10.mkv", "special.mkv"]);
+
+    let regex = RegexBuilder::new(r"s(?P<season>\d+)e(?P<episode>\d+)")
+        .case_insensitive(true)
+        .build()
+        .expect("failed to compile regex");
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(regex),
+        match_rename: Some("Season ${season|pad:2} - ${episode|pad:3}".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(run(args.clone()).is_ok());
+
+    check_filenames(
+        path.path(),
+        &["Season 01 - 002.mkv", "Season 02 - 010.mkv", "special.mkv"],
+    );
+
+    for broken in ["$3", "${episode}${title}", "$seasons", "${n@title}"] {
+        let args = Arguments {
+            match_rename: Some(broken.to_owned()),
+            ..args.clone()
+        };
+        let error = plan(&args).expect_err("a missing group to be refused");
+
+        assert!(error.to_string().contains("exist"), "{broken}: {error}");
+    }
+}
+
+// rena --match "(?P<season>\d+)x(\d+)" --match-rename "$1_x" Show/
+#[test]
+fn invalid_replacements() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["1x02.mkv", "notes.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(
+            Regex::new(r"(?P<season>\d+)x(\d+)\.mkv").expect("failed to compile regex"),
+        ),
+        ..Arguments::default()
+    };
+
+    for (pattern, expected) in [
+        ("$1_x", "`${1}_x`"),
+        ("$season_x", "`${season}_x`"),
+        ("$seasonal", "`${season}al`"),
+        ("${season_x}", "doesn't exist"),
+    ] {
+        let args = Arguments {
+            match_rename: Some(pattern.to_owned()),
+            ..args.clone()
+        };
+        let error = plan(&args).expect_err("an invalid reference to be refused");
+
+        assert!(error.to_string().contains(expected), "{pattern}: {error}");
+    }
+
+    for pattern in ["", ".", "..", "${season}/.."] {
+        let args = Arguments {
+            match_rename: Some(pattern.to_owned()),
+            ..args.clone()
+        };
+        let plan = plan(&args).expect("failed to plan");
+
+        assert!(
+            plan.problems
+                .iter()
+                .all(|x| matches!(x, Problem::InvalidName(_))),
+            "{pattern}: {:?}",
+            plan.problems
+        );
+        assert_eq!(plan.problems.len(), 1, "{pattern}");
+    }
+
+    let args = Arguments {
+        match_rename: Some("${season}x${2}.mkv".to_owned()),
+        ..args
+    };
+    let plan = plan(&args).expect("failed to plan");
+
+    assert!(plan.items.is_empty());
+    assert_eq!(plan.unchanged, [path.path().join("1x02.mkv")]);
+}
+
+// rena --match "(\w+)_(\d+)\.txt" --match-rename "$1/$2.txt" --create-dirs files/
+#[test]
+fn create_directories() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let journal = tempdir().expect("failed to obtain temporary directory");
+    let folder = path.path().join("files");
+
+    fs::create_dir(&folder).expect("failed to create directory");
+    setup_files_for_test(&folder, &["a_1.txt", "a_2.txt", "b_1.txt"]);
+
+    let args = Arguments {
+        folder: folder.clone(),
+        match_regex: Some(Regex::new(r"(\w+)_(\d+)\.txt").expect("failed to compile regex")),
+        journal: Some(PathBuf::from(journal.path())),
+        ..Arguments::default()
+    };
+
+    for (pattern, create_dirs) in [
+        ("$1/$2.txt", false),
+        ("../${1}_$2.txt", false),
+        ("../${1}_$2.txt", true),
+        ("$1/../../$2.txt", true),
+        ("/tmp/${1}_$2.txt", true),
+    ] {
+        let args = Arguments {
+            match_rename: Some(pattern.to_owned()),
+            create_dirs,
+            ..args.clone()
+        };
+        let plan = plan(&args).expect("failed to plan");
+
+        assert_eq!(plan.problems.len(), 3, "{pattern}: {:?}", plan.problems);
+        assert!(
+            plan.problems
+                .iter()
+                .all(|x| matches!(x, Problem::OtherDirectory(_) | Problem::OutsideFolder(_))),
+            "{pattern}: {:?}",
+            plan.problems
+        );
+        assert!(run(args).is_err());
+    }
+
+    check_filenames(&folder, &["a_1.txt", "a_2.txt", "b_1.txt"]);
+
+    let args = Arguments {
+        match_rename: Some("$1/./$2.txt".to_owned()),
+        create_dirs: true,
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(&folder, &["a/1.txt", "a/2.txt", "b/1.txt"]);
+
+    let args = UndoArguments {
+        journal: PathBuf::from(journal.path()),
+        ..UndoArguments::default()
+    };
+
+    assert!(undo(&args).is_ok());
+
+    check_filenames(&folder, &["a_1.txt", "a_2.txt", "b_1.txt"]);
+}
+
+// rena --match "(?-u)(.+) - (\d+)\.txt" --match-rename "$2 - $1.txt" files/
+#[cfg(unix)]
+#[test]
+#[allow(clippy::literal_string_with_formatting_args)]
+fn non_utf8_names() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let name = |x: &[u8]| path.path().join(OsStr::from_bytes(x));
+
+    File::create(name(b"caf\xe9 - 01.txt")).expect("failed to create file");
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"(?-u)(.+) - (\d+)\.txt").expect("failed to compile regex")),
+        ..Arguments::default()
+    };
+
+    for pattern in ["${1|upper}.txt", "${1:>10}.txt"] {
+        let args = Arguments {
+            match_rename: Some(pattern.to_owned()),
+            ..args.clone()
+        };
+
+        assert!(plan(&args).is_err(), "{pattern}");
+    }
+
+    let args = Arguments {
+        match_rename: Some("$2 - $1.txt".to_owned()),
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+    assert!(name(b"01 - caf\xe9.txt").exists());
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        template: Some(Template::parse("{stem|upper}{ext}").expect("failed to parse")),
+        ..Arguments::default()
+    };
+
+    assert!(plan(&args).is_err());
+
+    let args = Arguments {
+        template: Some(Template::parse("{n}_{stem}{ext}").expect("failed to parse")),
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+    assert!(name(b"0_01 - caf\xe9.txt").exists());
+}
+
+// rena --match "(\w+)\.txt" --match-rename "$1: what?.txt" --sanitize windows --sanitize-with ":=-" notes/
+#[test]
+fn sanitize_names() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["a.txt", "con.txt"]);
+
+    let mut windows = Sanitizer::new(Platform::Windows);
+
+    assert!(windows.add_replacement(":=?").is_err());
+    assert!(windows.add_replacement("a=b").is_err());
+    assert!(windows.add_replacement(":=-").is_ok());
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^(\w+)\.txt").expect("failed to compile regex")),
+        match_rename: Some("$1: what?.txt".to_owned()),
+        sanitize: Some(windows),
+        ..Arguments::default()
+    };
+    let plan = plan(&args).expect("failed to plan");
+
+    assert_eq!(plan.sanitized.len(), 2);
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["a- what_.txt", "con- what_.txt"]);
+
+    for (platform, name, expected) in [
+        (Platform::Posix, "a:b?.txt", "a:b?.txt"),
+        (Platform::MacOs, "a:b?.txt", "a_b?.txt"),
+        (Platform::Windows, "notes. . ", "notes"),
+        (Platform::Windows, "CON", "CON_"),
+        (Platform::Portable, "lpt1.tar.gz", "lpt1_.tar.gz"),
+        (Platform::Portable, "console.txt", "console.txt"),
+    ] {
+        let sanitized = Sanitizer::new(platform).sanitize(OsStr::new(name));
+
+        assert_eq!(sanitized, OsStr::new(expected), "{platform}: {name}");
+    }
+
+    let long = format!("{}.txt", "\u{e9}".repeat(200));
+    let sanitized = Sanitizer::new(Platform::Posix).sanitize(OsStr::new(&long));
+    let sanitized = sanitized
+        .to_str()
+        .expect("the name to be cut between characters");
+
+    assert!(sanitized.len() <= 255);
+    assert!(sanitized.ends_with("\u{e9}.txt"));
+}
+
+// rena --template "{stem}_{n:02}{ext}" --max-length 16 --ellipsis "~" docs/
+#[test]
+#[allow(clippy::literal_string_with_formatting_args)]
+fn max_length() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(
+        path.path(),
+        &[
+            "a long report, final.txt",
+            "a long report, draft.txt",
+            "b.txt",
+        ],
+    );
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        template: Some(Template::parse("{stem}_{n:02}{ext}").expect("failed to parse")),
+        max_length: Some(Limit {
+            ellipsis: "~".to_owned(),
+            ..Limit::parse("16").expect("failed to parse")
+        }),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(
+        path.path(),
+        &["a long re~00.txt", "a long re~01.txt", "b_02.txt"],
+    );
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(
+        path.path(),
+        &["\u{e9}t\u{e9} 2023.txt", "\u{e9}t\u{e9} 2024.txt"],
+    );
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^(\w+) (\d+)\.txt").expect("failed to compile regex")),
+        match_rename: Some("$1 summer $2 trip.txt".to_owned()),
+        max_length: Limit::parse("10c"),
+        ..Arguments::default()
+    };
+    let plan = plan(&args).expect("failed to plan");
+
+    assert!(plan.problems.is_empty(), "{:?}", plan.problems);
+    assert!(run(args).is_ok());
+
+    check_filenames(
+        path.path(),
+        &["\u{e9}t\u{e9} su.txt", "\u{e9}t\u{e9} ~1.txt"],
+    );
+
+    let limit = Limit::parse("5").expect("failed to parse");
+    let name = limit.truncate(OsStr::new("\u{e9}\u{e9}\u{e9}\u{e9}"), "");
+
+    assert_eq!(name.as_deref(), Some(OsStr::new("\u{e9}\u{e9}")));
+
+    // Too short for the extension and the ellipsis together.
+    let limit = Limit {
+        ellipsis: "\u{2026}".to_owned(),
+        ..Limit::parse("6").expect("failed to parse")
+    };
+    let name = OsStr::new("a long report.txt");
+
+    assert_eq!(
+        limit.truncate(name, "").as_deref(),
+        Some(OsStr::new("a .txt"))
+    );
+    assert_eq!(
+        limit.truncate(name, "~1").as_deref(),
+        Some(OsStr::new("~1.txt"))
+    );
+    assert_eq!(
+        limit.truncate(name, "~100").as_deref(),
+        Some(OsStr::new("a ~100"))
+    );
+    assert_eq!(limit.truncate(name, "~1000000"), None);
+
+    let limit = Limit::parse("4").expect("failed to parse");
+
+    assert_eq!(
+        limit.truncate(name, "").as_deref(),
+        Some(OsStr::new("a lo"))
+    );
+    assert!(Limit::parse("0").is_none());
+    assert!(Limit::parse("ten").is_none());
+}
+
+// rena --case snake --case-extension --match "(?i)\.jpg$" photos/
+#[test]
+fn case_conversion() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["My HolidayPhoto.JPG", "Notes.TXT"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"(?i)\.jpg$").expect("failed to compile regex")),
+        case: Some(Case::Snake),
+        case_extension: true,
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["my_holiday_photo.jpg", "Notes.TXT"]);
+
+    for (case, expected) in [
+        (Case::Lower, "the lord of the rings - part 2"),
+        (Case::Upper, "THE LORD OF THE RINGS - PART 2"),
+        (Case::Title, "The Lord of the Rings - Part 2"),
+        (Case::Sentence, "The lord of the rings - part 2"),
+        (Case::Snake, "the_lord_of_the_rings_part_2"),
+        (Case::Kebab, "the-lord-of-the-rings-part-2"),
+        (Case::Camel, "theLordOfTheRingsPart2"),
+        (Case::Pascal, "TheLordOfTheRingsPart2"),
+    ] {
+        assert_eq!(case.apply("the LORD of the rings - part 2"), expected);
+    }
+
+    assert_eq!(
+        Case::Snake.apply("HTTPServer v2_final"),
+        "http_server_v2_final"
+    );
+    assert_eq!(
+        Case::Title.apply("a tale of two cities"),
+        "A Tale of Two Cities"
+    );
+    assert_eq!(Case::Title.apply("what it's made of"), "What It's Made Of");
+}
+
+// rena --case lower --case-extension photos/
+#[test]
+fn case_only_renames() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["Photo.JPG", "Other.JPG"]);
+
+    // Two names for the same file that are both listed aren't a case-only
+    // rename, even though they only differ in case.
+    fs::hard_link(path.path().join("Other.JPG"), path.path().join("other.jpg"))
+        .expect("failed to create hard link");
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^[A-Z]").expect("failed to compile regex")),
+        case: Some(Case::Lower),
+        case_extension: true,
+        ..Arguments::default()
+    };
+    let plan = plan(&args).expect("failed to plan");
+
+    assert_eq!(
+        plan.problems,
+        [Problem::TargetExists(RenameItem {
+            original_path: path.path().join("Other.JPG"),
+            new_path: path.path().join("other.jpg"),
+            ..RenameItem::default()
+        })]
+    );
+
+    fs::remove_file(path.path().join("other.jpg")).expect("failed to remove hard link");
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["photo.jpg", "other.jpg"]);
+    assert!(!path.path().join("Photo.JPG").exists());
+}
+
+// rena --transliterate downloads/ && rena --slug _ downloads/
+#[test]
+fn transliterate_and_slug() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(
+        path.path(),
+        &[
+            "Caf\u{e9} \u{1f600} Party.JPG",
+            "\u{300c}\u{6771}\u{4eac}\u{300d}.txt",
+        ],
+    );
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        transliterate: Some("_".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(run(args.clone()).is_ok());
+
+    check_filenames(path.path(), &["Cafe grinning Party.JPG", "[Dong Jing].txt"]);
+
+    let args = Arguments {
+        transliterate: None,
+        slug: Some("_".to_owned()),
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["cafe_grinning_party.jpg", "dong_jing.txt"]);
+}
+
+// rena --normalize nfc -m "^café\.\w+$" photos/ && rena --normalize nfd -m "^(x)\.txt$" --match-rename "café.txt" photos/
+#[test]
+fn normalize() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["cafe\u{301}.txt", "x.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^caf\u{e9}\.\w+$").expect("failed to compile regex")),
+        normalize: Some(Normalization::Nfc),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["caf\u{e9}.txt", "x.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^(x)\.txt$").expect("failed to compile regex")),
+        match_rename: Some("caf\u{e9}.txt".to_owned()),
+        normalize: Some(Normalization::Nfd),
+        ..Arguments::default()
+    };
+    let plan = plan(&args).expect("failed to plan");
+
+    assert_eq!(
+        plan.problems,
+        [Problem::EquivalentExists {
+            item: RenameItem {
+                original_path: path.path().join("x.txt"),
+                new_path: path.path().join("cafe\u{301}.txt"),
+                ..RenameItem::default()
+            },
+            existing: path.path().join("caf\u{e9}.txt"),
+        }]
+    );
+    assert!(run(args).is_err());
+
+    check_filenames(path.path(), &["caf\u{e9}.txt", "x.txt"]);
+
+    assert_eq!(Normalization::Nfkc.apply("\u{fb01}le \u{2460}"), "file 1");
+    assert_eq!(Normalization::Nfd.apply("\u{e9}"), "e\u{301}");
+}
+
+// rena --match "^(\w)\.txt$" --match-rename "link/$1.txt" --create-dirs files/
+#[cfg(unix)]
+#[test]
+fn create_directories_through_symlink() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let outside = tempdir().expect("failed to obtain temporary directory");
+
+    setup_files_for_test(path.path(), &["a.txt"]);
+    std::os::unix::fs::symlink(outside.path(), path.path().join("link"))
+        .expect("failed to create symlink");
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^(\w)\.txt$").expect("failed to compile regex")),
+        match_rename: Some("link/$1.txt".to_owned()),
+        create_dirs: true,
+        ..Arguments::default()
+    };
+    let plan = plan(&args).expect("failed to plan");
+
+    assert!(
+        matches!(plan.problems[..], [Problem::OutsideFolder(_)]),
+        "{:?}",
+        plan.problems
+    );
+    assert!(run(args).is_err());
+
+    check_filenames(path.path(), &["a.txt"]);
+    assert!(!outside.path().join("a.txt").exists());
+}
+
+// a.txt -> b.txt -> c.txt with --on-conflict skip, where c.txt stays
+#[test]
+fn conflict_skip_chain() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["a.txt", "b.txt", "c.txt"]);
+
+    let item = |from: &str, to: &str| RenameItem {
+        original_path: path.path().join(from),
+        new_path: path.path().join(to),
+        ..RenameItem::default()
+    };
+    let (items, resolutions) = conflict::resolve(
+        vec![item("a.txt", "b.txt"), item("b.txt", "c.txt")],
+        ConflictPolicy::Skip,
+    )
+    .expect("failed to resolve conflicts");
+
+    assert!(items.is_empty(), "{items:?}");
+    assert_eq!(
+        resolutions,
+        [
+            Resolution::Skipped(item("b.txt", "c.txt")),
+            Resolution::Skipped(item("a.txt", "b.txt")),
+        ]
+    );
+}
+
+// rena --template "{n}-{stem}{ext}" --normalize nfc photos/
+#[test]
+fn normalize_template() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["cafe\u{301}.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        template: Some(Template::parse("{n}-{stem}{ext}").expect("failed to parse template")),
+        normalize: Some(Normalization::Nfc),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["0-caf\u{e9}.txt"]);
+}
+
+// rena --match "^(\w)\.txt$" --match-rename "<300 x>$1.txt" --sanitize portable files/
+#[test]
+fn sanitize_long_names() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["a.txt", "b.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"^(\w)\.txt$").expect("failed to compile regex")),
+        match_rename: Some(format!("{}$1.txt", "x".repeat(300))),
+        sanitize: Some(Sanitizer::new(Platform::Portable)),
+        ..Arguments::default()
+    };
+    let plan = plan(&args).expect("failed to plan");
+
+    assert!(plan.problems.is_empty(), "{:?}", plan.problems);
+    assert_eq!(plan.sanitized.len(), 2);
+    assert!(run(args).is_ok());
+
+    check_filenames(
+        path.path(),
+        &[
+            &format!("{}.txt", "x".repeat(251)),
+            &format!("{}~1.txt", "x".repeat(249)),
+        ],
+    );
+}
+
+// cd photos/ && rena --template "{parent}-{n}{ext}" .
+#[test]
+fn template_parent_of_current_directory() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let folder = path.path().join("photos");
+
+    fs::create_dir(&folder).expect("failed to create directory");
+    setup_files_for_test(&folder, &["a.jpg"]);
+
+    let current = std::env::current_dir().expect("failed to get current directory");
+
+    std::env::set_current_dir(&folder).expect("failed to change directory");
+
+    let args = Arguments {
+        folder: PathBuf::from("."),
+        template: Some(Template::parse("{parent}-{n}{ext}").expect("failed to parse")),
+        ..Arguments::default()
+    };
+    let result = run(args);
+
+    std::env::set_current_dir(current).expect("failed to change directory");
+
+    assert!(result.is_ok());
+
+    check_filenames(&folder, &["photos-0.jpg"]);
+}
+
+// A case change on a filesystem that ignores case, which fails halfway.
+#[test]
+fn case_change_rollback() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let from = path.path().join("photo.jpg");
+    let to = path.path().join("Photo.jpg");
+
+    setup_files_for_test(path.path(), &["photo.jpg"]);
+
+    let items = vec![RenameItem {
+        original_path: from.clone(),
+        new_path: to.clone(),
+        ..RenameItem::default()
+    }];
+    let steps = execute::order(&items, |_, _| true);
+
+    assert_eq!(steps.len(), 2, "{steps:?}");
+    assert_eq!(steps[0].from, from);
+    assert_eq!(steps[0].to, steps[1].from);
+    assert_eq!(steps[1].to, to);
+
+    let temp = steps[0].to.clone();
+
+    // Taken by something else, so the second step fails.
+    fs::write(&to, "other").expect("failed to write file");
+
+    let plan = RenamePlan {
+        items,
+        transactional: true,
+        ..RenamePlan::default()
+    };
+
+    assert!(execute::perform(steps, &plan, None).is_err());
+
+    check_filenames(path.path(), &["photo.jpg", "Photo.jpg"]);
+    assert!(!temp.exists());
+    assert_eq!(
+        fs::read_to_string(&to).expect("failed to read file"),
+        "other"
+    );
+}
+
+// rena --match "(?P<n>\d+)" --match-rename 'E$n' episodes/
+#[test]
+fn counter_group_name() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["ep 7.mkv"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"(?P<n>\d+)").expect("failed to compile regex")),
+        match_rename: Some("E$n".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(plan(&args).is_err());
+
+    let args = Arguments {
+        match_rename: Some("E$1".to_owned()),
+        ..args
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["ep E7.mkv"]);
+}
+
+// A library caller that sets `match_rename` without `match_regex`.
+#[test]
+fn match_rename_without_regex() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["a.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_rename: Some("b.txt".to_owned()),
+        ..Arguments::default()
+    };
+
+    assert!(plan(&args).is_err());
+}
+
+// rena --case lower files/, with a name that isn't valid UTF-8
+#[cfg(unix)]
+#[test]
+fn case_conversion_non_utf8() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let name = |x: &[u8]| path.path().join(OsStr::from_bytes(x));
+
+    File::create(name(b"CAF\xc9.txt")).expect("failed to create file");
+    setup_files_for_test(path.path(), &["Photo.txt"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        case: Some(Case::Lower),
+        ..Arguments::default()
+    };
+    let plan = plan(&args).expect("failed to plan");
+
+    assert_eq!(plan.items.len(), 1, "{:?}", plan.items);
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["photo.txt"]);
+    assert!(name(b"CAF\xc9.txt").exists());
+}
+
+// A rename out of a temporary name that fails, without --transactional.
+#[test]
+fn leftover_temporary_names() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let from = path.path().join("a.txt");
+    let temp = path.path().join(".rena-test.tmp");
+
+    setup_files_for_test(path.path(), &["a.txt"]);
+
+    let steps = vec![
+        Step {
+            from: from.clone(),
+            to: temp.clone(),
+            overwrite: false,
+        },
+        Step {
+            from: temp.clone(),
+            to: path.path().join("missing").join("b.txt"),
+            overwrite: false,
+        },
+    ];
+    let error = execute::perform(steps, &RenamePlan::default(), None)
+        .expect_err("a rename to a missing directory succeeded")
+        .to_string();
+
+    assert!(temp.exists());
+    assert!(
+        error.contains(&*temp.to_string_lossy()) && error.contains(&*from.to_string_lossy()),
+        "{error}"
+    );
+}
+
+// rena -m "ep (\w)" --match-rename 'E${n}' --origin 1 --padding 2 --normalize nfc episodes/
+#[test]
+fn normalize_counter() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["ep a.mkv", "ep b.mkv"]);
+
+    let args = Arguments {
+        folder: PathBuf::from(path.path()),
+        match_regex: Some(Regex::new(r"ep (\w)").expect("failed to compile regex")),
+        match_rename: Some("E${n}".to_owned()),
+        origin: 1,
+        padding: 2,
+        normalize: Some(Normalization::Nfc),
+        ..Arguments::default()
+    };
+
+    assert!(run(args).is_ok());
+
+    check_filenames(path.path(), &["E01.mkv", "E02.mkv"]);
+}
+
+// rena a/ && rena --transactional a/ (which fails and is rolled back) && rena undo
+#[test]
+fn undo_skips_rolled_back_run() {
+    let path = tempdir().expect("failed to obtain temporary directory");
+    let journal = tempdir().expect("failed to obtain temporary directory");
+    setup_files_for_test(path.path(), &["a.txt", "b.txt"]);
+
+    let plan = RenamePlan {
+        folder: PathBuf::from(path.path()),
+        items: vec![RenameItem {
+            original_path: path.path().join("a.txt"),
+            new_path: path.path().join("a2.txt"),
+            ..RenameItem::default()
+        }],
+        journal: Some(PathBuf::from(journal.path())),
+        ..RenamePlan::default()
+    };
+
+    assert!(execute(plan).is_ok());
+
+    let plan = RenamePlan {
+        folder: PathBuf::from(path.path()),
+        items: vec![
+            RenameItem {
+                original_path: path.path().join("b.txt"),
+                new_path: path.path().join("c.txt"),
+                ..RenameItem::default()
+            },
+            RenameItem {
+                original_path: path.path().join("missing.txt"),
+                new_path: path.path().join("d.txt"),
+                ..RenameItem::default()
+            },
+        ],
+        transactional: true,
+        journal: Some(PathBuf::from(journal.path())),
+        ..RenamePlan::default()
+    };
+
+    assert!(execute(plan).is_err());
+
+    check_filenames(path.path(), &["a2.txt", "b.txt"]);
+
+    let args = UndoArguments {
+        journal: PathBuf::from(journal.path()),
+        ..UndoArguments::default()
+    };
+
+    assert!(undo(&args).is_ok());
+
+    check_filenames(path.path(), &["a.txt", "b.txt"]);
+    assert!(!path.path().join("a2.txt").exists());
+    assert!(undo(&args).is_err());
+}